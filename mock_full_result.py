@@ -0,0 +1,30 @@
+import socket
+import time
+
+def send_full_results():
+    host = 'localhost'
+    port = 12345
+    
+    # Header: Event 21, 3000m
+    header = "UNOFFICIAL,21 Men 3000m,nwi,21,1,01,21-1-01,AUTO,3;".encode('utf-16le')
+    
+    # All 15 result variables from vmix.lss:
+    # Place, Lane, Id, Name, Affiliation, Time, Delta, Cumulative Split,
+    # Last Split, Laps To Go, License, ReacTime, Speed, Pace, Best Split
+    result1 = "1,3,201,Lead Runner,TEAM,4:12.31,,4:12.31,1:02.10,3,L201,0.152,5.22,3:11,1:01.85;".encode('utf-16le')
+    result2 = "2,5,202,Second Runner,CLUB,4:13.05,0.74,4:13.05,1:02.84,3,L202,0.161,5.20,3:12,1:02.02;".encode('utf-16le')
+
+    s = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
+    s.connect((host, port))
+    
+    print("Sending Header (Event 21)...")
+    s.sendall(header)
+    time.sleep(0.5)
+    
+    print("Sending full results...")
+    s.sendall(result1)
+    s.sendall(result2)
+    s.close()
+
+if __name__ == "__main__":
+    send_full_results()
@@ -0,0 +1,28 @@
+import socket
+import time
+
+def send_clock_frames():
+    host = 'localhost'
+    port = 12345
+    
+    s = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
+    s.connect((host, port))
+    
+    # Running time, as sent ~10 times per second
+    for tenth in range(5):
+        s.sendall(("%15.15s" % ("10.%d" % tenth)).encode('ascii'))
+        time.sleep(0.1)
+    
+    # TimeStopped: \01T\02%15.15s\03\04 followed by the F1 trigger
+    print("Sending TimeStopped...")
+    s.sendall(b"\x01T\x02" + ("%15.15s" % "10.47").encode('ascii') + b"\x03\x04")
+    s.sendall(b"Command=Trigger;Name=F1;Action=On;\n")
+    time.sleep(1)
+    
+    # TimeOfDay: same framing, fraction replaced by 4 spaces (\B4\I4)
+    print("Sending TimeOfDay...")
+    s.sendall(b"\x01T\x02" + ("%15.15s" % "14:23:05")[4:].encode('ascii') + b"    " + b"\x03\x04")
+    s.close()
+
+if __name__ == "__main__":
+    send_clock_frames()
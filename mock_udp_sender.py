@@ -0,0 +1,35 @@
+import socket
+import sys
+import time
+
+# Scoreboard output over UDP. Give the channel a "udp_port" in
+# bridge-config.json:
+#   "channels": [{ "name": "main", "port": 12345, "udp_port": 12350 }]
+# Run with "split" to cut every frame across two datagrams, which only comes
+# through with "udp_reassemble": true.
+HOST = 'localhost'
+PORT = 12350
+
+def send_results(split):
+    s = socket.socket(socket.AF_INET, socket.SOCK_DGRAM)
+
+    frames = [
+        "UNOFFICIAL,12 Women 200m,+0.8,12,1,01,12-1-01,AUTO,2;",
+        "1,4,301,First Sprinter,TEAM,23.41,,,,,,0.141,,,;",
+        "2,5,302,Second Sprinter,CLUB,23.87,0.46,,,,,0.155,,,;",
+    ]
+    for frame in frames:
+        data = frame.encode('utf-16le')
+        if split:
+            half = len(data) // 2 & ~1
+            s.sendto(data[:half], (HOST, PORT))
+            time.sleep(0.05)
+            s.sendto(data[half:], (HOST, PORT))
+        else:
+            s.sendto(data, (HOST, PORT))
+        print(f"Sent {frame}")
+        time.sleep(0.2)
+    s.close()
+
+if __name__ == "__main__":
+    send_results("split" in sys.argv[1:])
@@ -0,0 +1,36 @@
+import socket
+
+# Stand-in for vMix's TCP API on port 8099. Prints every command the bridge
+# sends and answers like vMix does.
+# Enable it in bridge-config.json with "vmix": { "api": { "enabled": true } }
+# and map some fields in vmix-titles.json:
+#   { "Scoreboard": { "Time.Text": "clock", "Name1.Text": "results[0].name" } }
+# Rules under "vmix": { "rules": [...] } show up here too (see src/rules.rs).
+HOST = '0.0.0.0'
+PORT = 8099
+
+def serve():
+    with socket.socket(socket.AF_INET, socket.SOCK_STREAM) as s:
+        s.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+        s.bind((HOST, PORT))
+        s.listen()
+        print(f"Fake vMix listening on {PORT}")
+        while True:
+            conn, addr = s.accept()
+            print(f"Bridge connected from {addr}")
+            with conn:
+                buffer = b""
+                while True:
+                    data = conn.recv(4096)
+                    if not data:
+                        break
+                    buffer += data
+                    while b"\r\n" in buffer:
+                        line, buffer = buffer.split(b"\r\n", 1)
+                        print(line.decode())
+                        if line.startswith(b"FUNCTION "):
+                            conn.sendall(b"FUNCTION OK Completed\r\n")
+            print("Bridge disconnected")
+
+if __name__ == "__main__":
+    serve()
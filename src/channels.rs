@@ -0,0 +1,155 @@
+use crate::clock;
+use crate::config::BridgeConfig;
+use crate::events::EventBus;
+use crate::lss::LssDecoder;
+use crate::pages;
+use crate::sources::SourceRegistry;
+use crate::state::{self, SharedState, HISTORY_FILE};
+use crate::tcp;
+use crate::udp;
+use crate::web;
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::runtime::Handle;
+
+// Several Lynx feeds at once (track camera, field results, a second finish
+// line), each on its own port with its own scoreboard and history:
+//
+// "channels": [
+//   { "name": "track", "port": 12345 },
+//   { "name": "field", "port": 12346, "history_file": "field-history.json" },
+//   { "name": "relay", "port": 12347, "udp_port": 12347, "udp_reassemble": true },
+//   { "name": "photo", "connect": "192.168.1.20:12345" }
+// ]
+//
+// With "connect" the bridge dials Lynx instead of listening on "port".
+//
+// The web pages and vMix XML for a channel live under /<name> (/field,
+// /field/vmix/live.xml, ...), and the first channel is also served at the
+// root like before. vMix push and rules follow the first channel. No
+// channels means one on 12345.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelConfig {
+    pub name: String,
+    pub port: u16,
+    pub history_file: String, // Empty is events-history-<name>.json
+    pub udp_port: Option<u16>, // Also take scoreboard output over UDP
+    pub udp_reassemble: bool, // Frames may be split across datagrams
+    pub connect: Option<String>, // host:port of a Lynx that serves scoreboard output
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self {
+            name: "main".to_string(),
+            port: 12345,
+            history_file: String::new(),
+            udp_port: None,
+            udp_reassemble: false,
+            connect: None,
+        }
+    }
+}
+
+// The channels to run, with the broken ones left out
+pub fn channel_configs(config: &BridgeConfig) -> Vec<ChannelConfig> {
+    if config.channels.is_empty() {
+        return vec![ChannelConfig { history_file: HISTORY_FILE.to_string(), ..Default::default() }];
+    }
+
+    // Taken by the routes at the root, so can't be channel names
+    let reserved = web::reserved_names();
+    let mut names = HashSet::new();
+    let mut ports = HashSet::new();
+    let mut udp_ports = HashSet::new();
+    let mut channels = Vec::new();
+    for channel in &config.channels {
+        let valid_name = !channel.name.is_empty()
+            && channel.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_name || reserved.contains(&channel.name.as_str()) {
+            warn!("Skipping channel '{}': names are letters, digits, - and _, and not one of {:?}", channel.name, reserved);
+            continue;
+        }
+        if !names.insert(channel.name.clone()) {
+            warn!("Skipping channel '{}': name is used twice", channel.name);
+            continue;
+        }
+        if channel.connect.is_none() && !ports.insert(channel.port) {
+            warn!("Skipping channel '{}': port {} is already taken", channel.name, channel.port);
+            continue;
+        }
+        if let Some(port) = channel.udp_port
+            && !udp_ports.insert(port) {
+            warn!("Skipping channel '{}': UDP port {} is already taken", channel.name, port);
+            continue;
+        }
+        let mut channel = channel.clone();
+        if channel.history_file.is_empty() {
+            channel.history_file = format!("events-history-{}.json", channel.name);
+        }
+        channels.push(channel);
+    }
+    channels
+}
+
+#[derive(Clone)]
+pub struct Channel {
+    pub name: String,
+    pub port: u16,
+    pub udp_port: Option<u16>,
+    pub connect: Option<String>,
+    pub state: SharedState,
+    pub bus: EventBus,
+    // The same events, once the state has taken them in
+    pub applied: EventBus,
+    pub sources: SourceRegistry,
+}
+
+impl Channel {
+    // Everything that belongs to one feed, from the listener to the history file
+    pub fn start(rt: &Handle, channel: &ChannelConfig, decoder: Option<Arc<LssDecoder>>, config: Arc<BridgeConfig>) -> Self {
+        let state = state::initialize_state(&channel.history_file);
+        let bus = EventBus::default();
+        let applied = EventBus::default();
+        let sources = SourceRegistry::new(config.sources.clone());
+
+        rt.spawn(state::apply_events(state.clone(), &bus, applied.clone()));
+        rt.spawn(state::persist_history(state.clone(), &bus, channel.history_file.clone()));
+        rt.spawn(sources.clone().watch());
+        // No decoder means the script failed to load, see main
+        if let Some(decoder) = decoder {
+            match &channel.connect {
+                Some(addr) => rt.spawn(tcp::start_client(bus.clone(), sources.clone(), decoder.clone(), config.clone(), addr.clone())),
+                None => rt.spawn(tcp::start_listener(bus.clone(), sources.clone(), decoder.clone(), config.clone(), channel.port)),
+            };
+            if let Some(port) = channel.udp_port {
+                rt.spawn(udp::start_listener(bus.clone(), sources.clone(), decoder, config.clone(), port, channel.udp_reassemble));
+            }
+        }
+        rt.spawn(clock::start_ticker(state.clone(), config.clone()));
+        // Results pages, rotating if configured
+        rt.spawn(pages::start_rotation(state.clone(), config));
+
+        let tcp = match &channel.connect {
+            Some(addr) => format!("TCP to {}", addr),
+            None => format!("TCP {}", channel.port),
+        };
+        match channel.udp_port {
+            Some(udp) => info!("Channel '{}' on {} and UDP {} (history in {})", channel.name, tcp, udp, channel.history_file),
+            None => info!("Channel '{}' on {} (history in {})", channel.name, tcp, channel.history_file),
+        }
+        Self {
+            name: channel.name.clone(),
+            port: channel.port,
+            udp_port: channel.udp_port,
+            connect: channel.connect.clone(),
+            state,
+            bus,
+            applied,
+            sources,
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use crate::config::BridgeConfig;
+use crate::state::{SharedState, Topic};
+use crate::time::TimeFormat;
+use log::info;
+use serde::{Serialize, Deserialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClockConfig {
+    pub rate_hz: u32, // How often the smoothed clock is published, 0 turns it off
+    pub max_extrapolate_ms: u64, // Stop guessing if Lynx goes quiet for this long
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self {
+            rate_hz: 20,
+            max_extrapolate_ms: 1000,
+        }
+    }
+}
+
+// The last running time Lynx sent and when we got it. Between packets the
+// clock is that time plus however long it's been since.
+#[derive(Debug, Clone, Default)]
+pub struct ClockModel {
+    anchor_ms: u64,
+    anchor_at: Option<Instant>,
+    precision: u8, // Match what Lynx is showing
+}
+
+impl ClockModel {
+    pub fn snap(&mut self, ms: u64, precision: u8) {
+        self.anchor_ms = ms;
+        self.anchor_at = Some(Instant::now());
+        self.precision = precision;
+    }
+
+    // Gun went off, count up from zero until Lynx tells us otherwise
+    pub fn restart(&mut self) {
+        self.anchor_ms = 0;
+        self.anchor_at = Some(Instant::now());
+    }
+
+    pub fn reset(&mut self) {
+        self.anchor_at = None;
+    }
+
+    pub fn now_ms(&self, max_extrapolate: Duration) -> Option<u64> {
+        let elapsed = self.anchor_at?.elapsed();
+        if elapsed > max_extrapolate {
+            return None;
+        }
+        Some(self.anchor_ms + elapsed.as_millis() as u64)
+    }
+}
+
+pub async fn start_ticker(state: SharedState, config: Arc<BridgeConfig>) {
+    let clock_config = &config.clock;
+    if clock_config.rate_hz == 0 {
+        info!("Clock smoothing disabled");
+        return;
+    }
+
+    let max_extrapolate = Duration::from_millis(clock_config.max_extrapolate_ms);
+    // A typo'd rate would spin the ticker, and a zero period panics in interval()
+    let period = (Duration::from_secs(1) / clock_config.rate_hz).max(Duration::from_millis(1));
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    info!("Publishing clock at {} Hz", clock_config.rate_hz);
+
+    loop {
+        interval.tick().await;
+        let mut s = state.write();
+
+        // Only tick while Lynx says the race is running, otherwise show what it sent
+        let smoothed = if s.running && !s.clock_frozen {
+            s.clock.now_ms(max_extrapolate)
+        } else {
+            None
+        };
+
+        let before = s.clock_time.clone();
+        match smoothed {
+            Some(ms) => {
+                let format = TimeFormat {
+                    precision: s.clock.precision,
+                    ..config.time_format.clone()
+                };
+                s.clock_time = format.format(Duration::from_millis(ms));
+                s.clock_ms = Some(ms);
+            }
+            None => {
+                s.clock_time = s.time.clone();
+                s.clock_ms = s.time_ms;
+            }
+        }
+        if s.clock_time != before {
+            s.notify(Topic::Clock);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use crate::channels::ChannelConfig;
+use crate::clock::ClockConfig;
+use crate::pages::PagesConfig;
+use crate::sources::SourcesConfig;
+use crate::time::TimeFormat;
+use crate::vmix::VmixConfig;
+use log::{error, info};
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::path::Path;
+
+pub const CONFIG_FILE: &str = "bridge-config.json";
+
+// Bridge settings, read from bridge-config.json next to the executable.
+// Anything missing from the file keeps its default.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct BridgeConfig {
+    pub time_format: TimeFormat,
+    // Re-render result times (e.g. "10.123" -> "10.12"), None keeps Lynx's text
+    pub result_time_format: Option<TimeFormat>,
+    pub clock: ClockConfig,
+    pub channels: Vec<ChannelConfig>,
+    pub pages: PagesConfig,
+    pub sources: SourcesConfig,
+    pub vmix: VmixConfig,
+}
+
+pub fn load_config() -> BridgeConfig {
+    if Path::new(CONFIG_FILE).exists() {
+        match fs::read_to_string(CONFIG_FILE) {
+            Ok(content) => match serde_json::from_str(&content) {
+                Ok(config) => {
+                    info!("Loaded config from {}", CONFIG_FILE);
+                    return config;
+                }
+                Err(e) => error!("Invalid {}: {} (using defaults)", CONFIG_FILE, e),
+            },
+            Err(e) => error!("Failed to read {}: {}", CONFIG_FILE, e),
+        }
+    }
+    BridgeConfig::default()
+}
@@ -0,0 +1,87 @@
+use crate::state::{AthleteResult, HeaderInfo, WindReading};
+use indexmap::IndexMap;
+use log::warn;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+// What the parser saw on the wire. The parser only publishes these; the
+// scoreboard state and history file each subscribe and decide for
+// themselves what to do with them. Rules read the state when they fire, so
+// they listen to a second bus the state forwards each event to once it is
+// applied. Web push, SSE and vMix output follow the state's ChangeFeed
+// instead, see there.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum LynxEvent {
+    ClockTick { time: String, ms: Option<u64>, precision: u8 },
+    GunStart { time: String, ms: Option<u64> },
+    TimeStopped { time: String, ms: Option<u64> },
+    TimeOfDay { time: String },
+    HeaderReceived {
+        header: String, // As sent
+        event_name: String,
+        event_number: String,
+        info: HeaderInfo,
+    },
+    ResultUpserted { result: Box<AthleteResult> },
+    ResultsCleared, // ;;Initialization (LayoutDraw), the board starts over
+    MessageSet { messages: Vec<String> },
+    WindMeasured { reading: WindReading },
+    SplitUpdated { index: u8, name: Option<String>, time: Option<String> },
+    // Any Command= line, e.g. Command=Trigger;Name=F1;Action=On; is
+    // command "Trigger" with params Name and Action
+    Command { command: String, params: IndexMap<String, String> },
+}
+
+// Results come in bursts of a few hundred lines, leave plenty of room
+const BUS_CAPACITY: usize = 4096;
+
+// Who put an event on the bus. Rules can change the board as well ("state":
+// "clear_results"), and mustn't fire again on their own changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    Lynx,
+    Rule,
+}
+
+#[derive(Debug, Clone)]
+pub struct EventBus(broadcast::Sender<(Origin, LynxEvent)>);
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self(broadcast::channel(BUS_CAPACITY).0)
+    }
+}
+
+impl EventBus {
+    pub fn publish(&self, event: LynxEvent) {
+        self.send(Origin::Lynx, event);
+    }
+
+    pub fn publish_from_rule(&self, event: LynxEvent) {
+        self.send(Origin::Rule, event);
+    }
+
+    // Passes an event on as it came in, origin and all
+    pub fn send(&self, origin: Origin, event: LynxEvent) {
+        // No subscribers yet is fine
+        let _ = self.0.send((origin, event));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<(Origin, LynxEvent)> {
+        self.0.subscribe()
+    }
+}
+
+// Next event for a subscriber, None once the bus is gone
+pub async fn next_event(rx: &mut broadcast::Receiver<(Origin, LynxEvent)>, who: &str) -> Option<(Origin, LynxEvent)> {
+    loop {
+        match rx.recv().await {
+            Ok(event) => return Some(event),
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                warn!("{} fell behind and missed {} events", who, n);
+            }
+            Err(broadcast::error::RecvError::Closed) => return None,
+        }
+    }
+}
@@ -0,0 +1,1044 @@
+// FinishLynx scoreboard script (.lss) loader.
+//
+// The script tells Lynx what to put on the wire. Rather than guessing the layout from
+// byte content, we read the same script and derive the frame signatures and CSV column
+// layouts from it, so edits to the script are picked up on the next start.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use thiserror::Error;
+
+pub const LSS_FILE: &str = "vmix.lss";
+
+// Shipped copy, used when no script sits next to the executable
+const BUNDLED_SCRIPT: &str = include_str!("../vmix.lss");
+
+// Group codes
+pub const GROUP_INITIALIZE: u8 = 0x10;
+pub const GROUP_TIME: u8 = 0x11;
+pub const GROUP_WIND: u8 = 0x12;
+pub const GROUP_RESULTS_HEADER: u8 = 0x13;
+pub const GROUP_RESULT: u8 = 0x14;
+pub const GROUP_MESSAGE_HEADER: u8 = 0x15;
+pub const GROUP_MESSAGE: u8 = 0x16;
+pub const GROUP_BREAK_TIME: u8 = 0x17;
+pub const GROUP_BREAK_NAME: u8 = 0x18;
+
+// Variable codes shared by most groups
+pub const VAR_NONE: u8 = 0x00;
+pub const VAR_FORMATTED: u8 = 0x01;
+pub const VAR_BINARY: u8 = 0x02;
+
+// Results header variables (\13)
+pub const VAR_HEADER_STATUS: u8 = 0x01;
+pub const VAR_HEADER_EVENT_NAME: u8 = 0x02;
+pub const VAR_HEADER_WIND: u8 = 0x03;
+pub const VAR_HEADER_EVENT_NUMBER: u8 = 0x04;
+pub const VAR_HEADER_ROUND: u8 = 0x05;
+pub const VAR_HEADER_HEAT: u8 = 0x06;
+pub const VAR_HEADER_START_TYPE: u8 = 0x07;
+pub const VAR_HEADER_PARTICIPANTS: u8 = 0x08;
+
+// Result variables (\14)
+pub const VAR_RESULT_PLACE: u8 = 0x01;
+pub const VAR_RESULT_LANE: u8 = 0x02;
+pub const VAR_RESULT_ID: u8 = 0x03;
+pub const VAR_RESULT_NAME: u8 = 0x04;
+pub const VAR_RESULT_AFFILIATION: u8 = 0x05;
+pub const VAR_RESULT_TIME: u8 = 0x06;
+pub const VAR_RESULT_DELTA: u8 = 0x07;
+pub const VAR_RESULT_CUMULATIVE_SPLIT: u8 = 0x08;
+pub const VAR_RESULT_LAST_SPLIT: u8 = 0x09;
+pub const VAR_RESULT_LAPS_TO_GO: u8 = 0x0a;
+pub const VAR_RESULT_LICENSE: u8 = 0x0b;
+pub const VAR_RESULT_REACTION_TIME: u8 = 0x0c;
+pub const VAR_RESULT_SPEED: u8 = 0x0d;
+pub const VAR_RESULT_PACE: u8 = 0x0e;
+pub const VAR_RESULT_BEST_SPLIT: u8 = 0x0f;
+
+#[derive(Debug, Error)]
+pub enum LssError {
+    #[error("failed to read {path}: {source}")]
+    Io { path: String, source: std::io::Error },
+    #[error("line {line}: unknown section ';;{name}'")]
+    UnknownSection { line: usize, name: String },
+    #[error("line {line}: format line appears before any section header")]
+    NoSection { line: usize },
+    #[error("line {line}: format line must begin with a group code and a variable code (e.g. \\14\\01)")]
+    MissingCodes { line: usize },
+    #[error("line {line}: group \\{group:02x} is not allowed in section ;;{section}")]
+    InvalidGroup { line: usize, section: Section, group: u8 },
+    #[error("line {line}: variable \\{variable:02x} does not exist for group \\{group:02x}")]
+    InvalidVariable { line: usize, group: u8, variable: u8 },
+    #[error("line {line}: bad escape sequence: {detail}")]
+    BadEscape { line: usize, detail: String },
+    #[error("line {line}: line requests a variable but has no %s specifier")]
+    MissingSpecifier { line: usize },
+    #[error("line {line}: only one %s specifier is allowed per line")]
+    MultipleSpecifiers { line: usize },
+    #[error("line {line}: unsupported printf specifier '%{spec}' (only %s is allowed)")]
+    UnsupportedSpecifier { line: usize, spec: char },
+    #[error("section ;;{section}: {detail}")]
+    Layout { section: Section, detail: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Initialization,
+    TimeRunning,
+    TimeStopped,
+    TimeGun,
+    TimeBreak,
+    TimeUpdate,
+    TimeOfDay,
+    Wind,
+    ResultsHeader,
+    ResultsTrailer,
+    StartListHeader,
+    StartListTrailer,
+    Result,
+    StartList,
+    MessageHeader,
+    MessageTrailer,
+    Message,
+}
+
+impl Section {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let section = match name {
+            "Initialization" => Section::Initialization,
+            "TimeRunning" => Section::TimeRunning,
+            "TimeStopped" => Section::TimeStopped,
+            "TimeGun" => Section::TimeGun,
+            "TimeBreak" => Section::TimeBreak,
+            "TimeUpdate" => Section::TimeUpdate,
+            "TimeOfDay" => Section::TimeOfDay,
+            "Wind" => Section::Wind,
+            "ResultsHeader" => Section::ResultsHeader,
+            "ResultsTrailer" => Section::ResultsTrailer,
+            "StartListHeader" => Section::StartListHeader,
+            "StartListTrailer" => Section::StartListTrailer,
+            "Result" => Section::Result,
+            "StartList" => Section::StartList,
+            "MessageHeader" => Section::MessageHeader,
+            "MessageTrailer" => Section::MessageTrailer,
+            "Message" => Section::Message,
+            _ => return None,
+        };
+        Some(section)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Section::Initialization => "Initialization",
+            Section::TimeRunning => "TimeRunning",
+            Section::TimeStopped => "TimeStopped",
+            Section::TimeGun => "TimeGun",
+            Section::TimeBreak => "TimeBreak",
+            Section::TimeUpdate => "TimeUpdate",
+            Section::TimeOfDay => "TimeOfDay",
+            Section::Wind => "Wind",
+            Section::ResultsHeader => "ResultsHeader",
+            Section::ResultsTrailer => "ResultsTrailer",
+            Section::StartListHeader => "StartListHeader",
+            Section::StartListTrailer => "StartListTrailer",
+            Section::Result => "Result",
+            Section::StartList => "StartList",
+            Section::MessageHeader => "MessageHeader",
+            Section::MessageTrailer => "MessageTrailer",
+            Section::Message => "Message",
+        }
+    }
+
+    // Valid group codes per section, as listed in the script notes
+    fn allowed_groups(&self) -> &'static [u8] {
+        match self {
+            Section::Initialization => &[GROUP_INITIALIZE, GROUP_RESULTS_HEADER, GROUP_BREAK_TIME],
+            Section::TimeRunning
+            | Section::TimeStopped
+            | Section::TimeGun
+            | Section::TimeBreak
+            | Section::TimeUpdate
+            | Section::TimeOfDay => &[GROUP_TIME, GROUP_RESULTS_HEADER, GROUP_BREAK_TIME],
+            Section::Wind => &[GROUP_WIND, GROUP_RESULTS_HEADER, GROUP_BREAK_TIME],
+            Section::ResultsHeader
+            | Section::ResultsTrailer
+            | Section::StartListHeader
+            | Section::StartListTrailer => &[GROUP_RESULTS_HEADER, GROUP_BREAK_TIME],
+            Section::Result | Section::StartList => &[GROUP_RESULTS_HEADER, GROUP_RESULT, GROUP_BREAK_TIME],
+            Section::MessageHeader | Section::MessageTrailer => {
+                &[GROUP_RESULTS_HEADER, GROUP_MESSAGE_HEADER, GROUP_BREAK_TIME]
+            }
+            Section::Message => &[GROUP_RESULTS_HEADER, GROUP_MESSAGE_HEADER, GROUP_MESSAGE, GROUP_BREAK_TIME],
+        }
+    }
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+fn variable_exists(group: u8, variable: u8) -> bool {
+    match group {
+        GROUP_INITIALIZE | GROUP_MESSAGE_HEADER => variable == VAR_NONE,
+        GROUP_TIME | GROUP_WIND => variable <= VAR_BINARY,
+        GROUP_RESULTS_HEADER => variable <= VAR_HEADER_PARTICIPANTS,
+        GROUP_RESULT => variable <= VAR_RESULT_BEST_SPLIT,
+        GROUP_MESSAGE => variable <= VAR_FORMATTED,
+        // Break time nn / break name nn
+        GROUP_BREAK_TIME | GROUP_BREAK_NAME => true,
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    // Bytes sent exactly as they appear
+    Text(Vec<u8>),
+    // Where the requested variable is inserted (%s)
+    Value,
+    // Embedded register/cursor command, e.g. \L0 or \Ui\03
+    Command { name: String, arg: u8 },
+}
+
+#[derive(Debug, Clone)]
+pub struct FormatLine {
+    pub group: u8,
+    pub variable: u8,
+    pub segments: Vec<Segment>,
+}
+
+impl FormatLine {
+    fn text_before_value(&self) -> Vec<u8> {
+        self.segments
+            .iter()
+            .take_while(|s| **s != Segment::Value)
+            .filter_map(|s| match s {
+                Segment::Text(bytes) => Some(bytes.as_slice()),
+                _ => None,
+            })
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    fn text_after_value(&self) -> Vec<u8> {
+        self.segments
+            .iter()
+            .skip_while(|s| **s != Segment::Value)
+            .skip(1)
+            .filter_map(|s| match s {
+                Segment::Text(bytes) => Some(bytes.as_slice()),
+                _ => None,
+            })
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    fn text(&self) -> Vec<u8> {
+        let mut out = self.text_before_value();
+        out.extend(self.text_after_value());
+        out
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScriptSection {
+    pub kind: Section,
+    pub lines: Vec<FormatLine>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LssScript {
+    pub sections: Vec<ScriptSection>,
+}
+
+impl LssScript {
+    pub fn load(path: &str) -> Result<Self, LssError> {
+        let content = fs::read_to_string(path).map_err(|source| LssError::Io {
+            path: path.to_string(),
+            source,
+        })?;
+        Self::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<Self, LssError> {
+        let mut script = LssScript::default();
+
+        for (idx, raw) in content.lines().enumerate() {
+            let line_no = idx + 1;
+            // Whitespace is significant in format lines, only strip the line ending
+            let line = raw.strip_suffix('\r').unwrap_or(raw);
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix(";;") {
+                let name = name.trim();
+                let kind = Section::from_name(name).ok_or_else(|| LssError::UnknownSection {
+                    line: line_no,
+                    name: name.to_string(),
+                })?;
+                script.sections.push(ScriptSection { kind, lines: Vec::new() });
+                continue;
+            }
+
+            if line.starts_with(';') {
+                continue;
+            }
+
+            let section = script
+                .sections
+                .last_mut()
+                .ok_or(LssError::NoSection { line: line_no })?;
+            let format_line = parse_format_line(line, line_no)?;
+
+            // Break names go wherever break times may
+            let allowed = section.kind.allowed_groups();
+            let group_ok = allowed.contains(&format_line.group)
+                || (format_line.group == GROUP_BREAK_NAME && allowed.contains(&GROUP_BREAK_TIME));
+            if !group_ok {
+                return Err(LssError::InvalidGroup {
+                    line: line_no,
+                    section: section.kind,
+                    group: format_line.group,
+                });
+            }
+            section.lines.push(format_line);
+        }
+
+        Ok(script)
+    }
+
+    // Sections may occur more than once, so walk all of them
+    pub fn lines(&self, kind: Section) -> impl Iterator<Item = &FormatLine> {
+        self.sections
+            .iter()
+            .filter(move |s| s.kind == kind)
+            .flat_map(|s| s.lines.iter())
+    }
+
+    pub fn has_section(&self, kind: Section) -> bool {
+        self.lines(kind).next().is_some()
+    }
+}
+
+enum Piece {
+    Byte(u8),
+    Command { name: String, arg: u8 },
+}
+
+fn hex_value(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        _ => None,
+    }
+}
+
+// Reads the argument of an embedded command: either \hh or a single digit
+fn read_command_arg(bytes: &[u8], pos: &mut usize, line: usize, name: &str) -> Result<u8, LssError> {
+    match bytes.get(*pos) {
+        Some(b'\\') => {
+            let hi = bytes.get(*pos + 1).and_then(|c| hex_value(*c));
+            let lo = bytes.get(*pos + 2).and_then(|c| hex_value(*c));
+            match (hi, lo) {
+                (Some(hi), Some(lo)) => {
+                    *pos += 3;
+                    Ok(hi * 16 + lo)
+                }
+                _ => Err(LssError::BadEscape {
+                    line,
+                    detail: format!("command \\{} expects a \\hh argument", name),
+                }),
+            }
+        }
+        Some(c) if c.is_ascii_digit() => {
+            *pos += 1;
+            Ok(c - b'0')
+        }
+        _ => Err(LssError::BadEscape {
+            line,
+            detail: format!("command \\{} is missing its argument", name),
+        }),
+    }
+}
+
+fn unescape(text: &str, line: usize) -> Result<Vec<Piece>, LssError> {
+    let bytes = text.as_bytes();
+    let mut pieces = Vec::new();
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let c = bytes[pos];
+        if c != b'\\' {
+            pieces.push(Piece::Byte(c));
+            pos += 1;
+            continue;
+        }
+
+        let Some(&next) = bytes.get(pos + 1) else {
+            return Err(LssError::BadEscape {
+                line,
+                detail: "line ends with a lone backslash".to_string(),
+            });
+        };
+
+        // \hh - arbitrary 8 bit value, lowercase hex only
+        if let (Some(hi), Some(lo)) = (hex_value(next), bytes.get(pos + 2).and_then(|c| hex_value(*c))) {
+            pieces.push(Piece::Byte(hi * 16 + lo));
+            pos += 3;
+            continue;
+        }
+
+        pos += 2;
+        let name = match next {
+            b'U' | b'X' if bytes.get(pos) == Some(&b'i') => {
+                pos += 1;
+                format!("{}i", next as char)
+            }
+            b'I' if bytes.get(pos) == Some(&b'c') => {
+                pos += 1;
+                "Ic".to_string()
+            }
+            b'U' | b'X' | b'=' | b'*' | b'/' | b'#' | b'+' | b'-' | b'<' | b'>' | b'&' | b'|' | b'^'
+            | b'~' | b'F' | b'O' | b'S' | b'P' | b'B' | b'D' | b'I' | b'L' | b'R' => (next as char).to_string(),
+            // Anything else is an escaped literal, e.g. \% or \\
+            _ => {
+                pieces.push(Piece::Byte(next));
+                continue;
+            }
+        };
+        let arg = read_command_arg(bytes, &mut pos, line, &name)?;
+        pieces.push(Piece::Command { name, arg });
+    }
+
+    Ok(pieces)
+}
+
+// Splits printf text around its %s specifier. Returns the segments and how many specifiers were found.
+fn split_specifiers(text: &[u8], line: usize, out: &mut Vec<Segment>) -> Result<usize, LssError> {
+    let mut found = 0;
+    let mut current = Vec::new();
+    let mut pos = 0;
+
+    while pos < text.len() {
+        if text[pos] != b'%' {
+            current.push(text[pos]);
+            pos += 1;
+            continue;
+        }
+        if text.get(pos + 1) == Some(&b'%') {
+            current.push(b'%');
+            pos += 2;
+            continue;
+        }
+
+        // %[flags][width][.precision]s - the padding is applied by Lynx, we only need the position
+        let mut end = pos + 1;
+        while end < text.len() && matches!(text[end], b'-' | b'+' | b' ' | b'#' | b'0'..=b'9' | b'.') {
+            end += 1;
+        }
+        match text.get(end) {
+            Some(b's') => {}
+            Some(&spec) => return Err(LssError::UnsupportedSpecifier { line, spec: spec as char }),
+            None => return Err(LssError::UnsupportedSpecifier { line, spec: '%' }),
+        }
+
+        if !current.is_empty() {
+            out.push(Segment::Text(std::mem::take(&mut current)));
+        }
+        out.push(Segment::Value);
+        found += 1;
+        pos = end + 1;
+    }
+
+    if !current.is_empty() {
+        out.push(Segment::Text(current));
+    }
+    Ok(found)
+}
+
+fn parse_format_line(text: &str, line: usize) -> Result<FormatLine, LssError> {
+    let mut pieces = unescape(text, line)?.into_iter();
+
+    let (group, variable) = match (pieces.next(), pieces.next()) {
+        (Some(Piece::Byte(group)), Some(Piece::Byte(variable))) if text.starts_with('\\') => (group, variable),
+        _ => return Err(LssError::MissingCodes { line }),
+    };
+    if !variable_exists(group, variable) {
+        return Err(LssError::InvalidVariable { line, group, variable });
+    }
+
+    // Collapse consecutive bytes into text runs, commands break them up
+    let mut raw_segments: Vec<Segment> = Vec::new();
+    for piece in pieces {
+        match piece {
+            Piece::Byte(b) => match raw_segments.last_mut() {
+                Some(Segment::Text(bytes)) => bytes.push(b),
+                _ => raw_segments.push(Segment::Text(vec![b])),
+            },
+            Piece::Command { name, arg } => raw_segments.push(Segment::Command { name, arg }),
+        }
+    }
+
+    // Lines without a variable are not fed to printf, so '%' is just a character there
+    if variable == VAR_NONE {
+        return Ok(FormatLine { group, variable, segments: raw_segments });
+    }
+
+    let mut segments = Vec::new();
+    let mut specifiers = 0;
+    for segment in raw_segments {
+        match segment {
+            Segment::Text(bytes) => specifiers += split_specifiers(&bytes, line, &mut segments)?,
+            other => segments.push(other),
+        }
+    }
+
+    match specifiers {
+        0 => Err(LssError::MissingSpecifier { line }),
+        1 => Ok(FormatLine { group, variable, segments }),
+        _ => Err(LssError::MultipleSpecifiers { line }),
+    }
+}
+
+// Text wrapped around a single value, e.g. \01T\02 ... \03\04 for stopped time
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Frame {
+    pub variable: u8,
+    // 4 byte little endian integer instead of text (\11\02, \12\02)
+    pub binary: bool,
+    pub prefix: Vec<u8>,
+    pub suffix: Vec<u8>,
+    // Spaces the line's \I commands append to the value. TimeOfDay uses
+    // \B4\I4 to swap the fraction for padding, which is all that tells it
+    // apart from TimeStopped on the wire.
+    pub trailing_pad: usize,
+}
+
+impl Frame {
+    // Locates a complete frame in the buffer: (start, value range, end)
+    pub fn find(&self, buffer: &[u8]) -> Option<(usize, std::ops::Range<usize>, usize)> {
+        if self.prefix.is_empty() { return None; }
+        let start = buffer.windows(self.prefix.len()).position(|w| w == self.prefix.as_slice())?;
+        let value_start = start + self.prefix.len();
+        let value_end = if self.binary {
+            // 4 byte little endian integer, the bytes may look like anything
+            let value_end = value_start + 4;
+            if buffer.len() < value_end + self.suffix.len()
+                || !buffer[value_end..].starts_with(&self.suffix)
+            {
+                return None;
+            }
+            value_end
+        } else if self.suffix.is_empty() {
+            buffer.len()
+        } else {
+            buffer[value_start..]
+                .windows(self.suffix.len())
+                .position(|w| w == self.suffix.as_slice())?
+                + value_start
+        };
+        Some((start, value_start..value_end, value_end + self.suffix.len()))
+    }
+}
+
+// Break time (\17\nn) or break name (\18\nn) line for split nn
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BreakFrame {
+    pub index: u8,
+    pub is_name: bool,
+    pub frame: Frame,
+}
+
+// One CSV column. Usually a single variable, but the header builds its
+// combined id (15B-1-01) from several.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Column {
+    pub variables: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RecordLayout {
+    pub columns: Vec<Column>,
+}
+
+impl RecordLayout {
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    // Index of the column carrying just this variable
+    pub fn column_of(&self, variable: u8) -> Option<usize> {
+        self.columns.iter().position(|c| c.variables == [variable])
+    }
+
+    // Column built from several variables, like the header's 15B-1-01
+    pub fn combined_column(&self) -> Option<usize> {
+        self.columns.iter().position(|c| c.variables.len() > 1)
+    }
+
+    pub fn variable_at(&self, index: usize) -> Option<u8> {
+        match self.columns.get(index) {
+            Some(column) if column.variables.len() == 1 => Some(column.variables[0]),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LssDecoder {
+    pub separator: char,
+    pub terminator: char,
+    pub header: RecordLayout,
+    pub result: RecordLayout,
+    pub time_running: Option<Frame>,
+    pub time_gun: Option<Frame>,
+    pub time_stopped: Option<Frame>,
+    pub time_of_day: Option<Frame>,
+    pub wind: Option<Frame>,
+    pub breaks: Vec<BreakFrame>,
+    pub message_header: Vec<u8>,
+    pub message_line: Option<Frame>,
+    pub message_trailer: Vec<u8>,
+    // Commands sent by ;;Initialization (e.g. Command=LayoutDraw;Clear=2;)
+    pub initialization: Vec<String>,
+}
+
+impl LssDecoder {
+    pub fn from_script(script: &LssScript) -> Result<Self, LssError> {
+        let mut decoder = LssDecoder {
+            separator: ',',
+            terminator: ';',
+            ..Default::default()
+        };
+
+        let result_section = if script.has_section(Section::Result) { Section::Result } else { Section::StartList };
+        let header_section = if script.has_section(Section::ResultsHeader) {
+            Section::ResultsHeader
+        } else {
+            Section::StartListHeader
+        };
+
+        // Both sections go over the same wire, so the delimiters must agree
+        let mut delimiters: Option<(Section, char, char)> = None;
+        for section in [result_section, header_section] {
+            if !script.has_section(section) {
+                continue;
+            }
+            let (separator, terminator) = record_delimiters(script, section)?;
+            match delimiters {
+                Some((first, sep, term)) if sep != separator || term != terminator => {
+                    return Err(LssError::Layout {
+                        section,
+                        detail: format!(
+                            "uses '{}' and '{}' as separator/terminator but ;;{} uses '{}' and '{}'",
+                            separator, terminator, first, sep, term
+                        ),
+                    });
+                }
+                Some(_) => {}
+                None => delimiters = Some((section, separator, terminator)),
+            }
+        }
+        if let Some((_, separator, terminator)) = delimiters {
+            decoder.separator = separator;
+            decoder.terminator = terminator;
+        }
+
+        decoder.result = record_layout(script, result_section, GROUP_RESULT, decoder.separator, decoder.terminator)?;
+        decoder.header = record_layout(
+            script,
+            header_section,
+            GROUP_RESULTS_HEADER,
+            decoder.separator,
+            decoder.terminator,
+        )?;
+
+        // The bridge cannot tell start list lines from result lines
+        if script.has_section(Section::Result) && script.has_section(Section::StartList) {
+            let start_list = record_layout(script, Section::StartList, GROUP_RESULT, decoder.separator, decoder.terminator)?;
+            if start_list.columns != decoder.result.columns {
+                return Err(LssError::Layout {
+                    section: Section::StartList,
+                    detail: "column layout differs from ;;Result".to_string(),
+                });
+            }
+        }
+
+        if !decoder.result.is_empty()
+            && ![VAR_RESULT_PLACE, VAR_RESULT_LANE, VAR_RESULT_ID]
+                .iter()
+                .any(|v| decoder.result.column_of(*v).is_some())
+        {
+            return Err(LssError::Layout {
+                section: result_section,
+                detail: "needs a Place (\\14\\01), Lane (\\14\\02) or Id (\\14\\03) column to match results".to_string(),
+            });
+        }
+        if !decoder.header.is_empty() && decoder.header.column_of(VAR_HEADER_STATUS).is_none() {
+            return Err(LssError::Layout {
+                section: header_section,
+                detail: "needs the OFFICIAL/UNOFFICIAL column (\\13\\01) to recognise headers".to_string(),
+            });
+        }
+
+        decoder.time_running = value_frame(script, Section::TimeRunning, GROUP_TIME);
+        decoder.time_gun = value_frame(script, Section::TimeGun, GROUP_TIME);
+        decoder.time_stopped = value_frame(script, Section::TimeStopped, GROUP_TIME);
+        decoder.time_of_day = value_frame(script, Section::TimeOfDay, GROUP_TIME);
+
+        // Four raw bytes can't be spotted without framing
+        for (section, frame) in [
+            (Section::TimeRunning, &decoder.time_running),
+            (Section::TimeStopped, &decoder.time_stopped),
+            (Section::TimeOfDay, &decoder.time_of_day),
+        ] {
+            if frame.as_ref().is_some_and(|f| f.binary && f.prefix.is_empty()) {
+                return Err(LssError::Layout {
+                    section,
+                    detail: "binary time (\\11\\02) needs text before the %s to be recognised".to_string(),
+                });
+            }
+        }
+
+        // Only the running time may go out bare, everything else is spotted by its leading text
+        for (section, frame) in [
+            (Section::TimeGun, &decoder.time_gun),
+            (Section::TimeStopped, &decoder.time_stopped),
+            (Section::TimeOfDay, &decoder.time_of_day),
+        ] {
+            if frame.as_ref().is_some_and(|f| f.prefix.is_empty()) {
+                return Err(LssError::Layout {
+                    section,
+                    detail: "time line needs text before the %s so it can be told apart from the running time".to_string(),
+                });
+            }
+        }
+        // A bare running time is picked out of whitespace, other text after it would stick to the value
+        if let Some(frame) = &decoder.time_running
+            && frame.prefix.is_empty()
+            && !frame.suffix.iter().all(u8::is_ascii_whitespace)
+        {
+            return Err(LssError::Layout {
+                section: Section::TimeRunning,
+                detail: "running time without text before the %s can only be followed by whitespace".to_string(),
+            });
+        }
+
+        decoder.wind = value_frame(script, Section::Wind, GROUP_WIND);
+        if decoder.wind.as_ref().is_some_and(|f| f.prefix.is_empty()) {
+            return Err(LssError::Layout {
+                section: Section::Wind,
+                detail: "wind line needs text before the %s so it can be told apart from the clock".to_string(),
+            });
+        }
+
+        // Break lines may sit in any section
+        for section in &script.sections {
+            for line in &section.lines {
+                if !matches!(line.group, GROUP_BREAK_TIME | GROUP_BREAK_NAME) || line.variable == VAR_NONE {
+                    continue;
+                }
+                let frame = Frame {
+                    variable: line.variable,
+                    binary: false,
+                    prefix: line.text_before_value(),
+                    suffix: line.text_after_value(),
+                    trailing_pad: trailing_pad(line),
+                };
+                if frame.prefix.is_empty() {
+                    return Err(LssError::Layout {
+                        section: section.kind,
+                        detail: format!("break line \\{:02x}\\{:02x} needs text before the %s to be recognised", line.group, line.variable),
+                    });
+                }
+                if decoder.breaks.iter().any(|b| b.frame.prefix == frame.prefix) {
+                    return Err(LssError::Layout {
+                        section: section.kind,
+                        detail: format!("break line \\{:02x}\\{:02x} has the same leading text as another break line", line.group, line.variable),
+                    });
+                }
+                decoder.breaks.push(BreakFrame {
+                    index: line.variable,
+                    is_name: line.group == GROUP_BREAK_NAME,
+                    frame,
+                });
+            }
+        }
+
+        decoder.message_header = section_text(script, Section::MessageHeader, GROUP_MESSAGE_HEADER);
+        decoder.message_trailer = section_text(script, Section::MessageTrailer, GROUP_MESSAGE_HEADER);
+        decoder.message_line = value_frame(script, Section::Message, GROUP_MESSAGE);
+        if script.has_section(Section::Message) && decoder.message_header.is_empty() {
+            return Err(LssError::Layout {
+                section: Section::MessageHeader,
+                detail: "message lines are sent but there is no header to recognise them by".to_string(),
+            });
+        }
+
+        decoder.initialization = script
+            .lines(Section::Initialization)
+            .filter(|l| l.group == GROUP_INITIALIZE)
+            .map(|l| String::from_utf8_lossy(&l.text()).trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        Ok(decoder)
+    }
+
+    // The copy of vmix.lss compiled into the binary
+    pub fn bundled() -> Self {
+        let script = LssScript::parse(BUNDLED_SCRIPT).expect("bundled vmix.lss must parse");
+        Self::from_script(&script).expect("bundled vmix.lss must produce a decoder")
+    }
+}
+
+// Loads the script from disk, falling back to the bundled copy when there is none
+pub fn load_decoder(path: &str) -> Result<LssDecoder, LssError> {
+    if !Path::new(path).exists() {
+        return Ok(LssDecoder::bundled());
+    }
+    let script = LssScript::load(path)?;
+    LssDecoder::from_script(&script)
+}
+
+fn value_frame(script: &LssScript, section: Section, group: u8) -> Option<Frame> {
+    script
+        .lines(section)
+        .find(|l| l.group == group && l.variable != VAR_NONE)
+        .map(|l| Frame {
+            variable: l.variable,
+            binary: l.variable == VAR_BINARY && matches!(group, GROUP_TIME | GROUP_WIND),
+            prefix: l.text_before_value(),
+            suffix: l.text_after_value(),
+            trailing_pad: trailing_pad(l),
+        })
+}
+
+// Sum of \I inserts after the value while the cursor is still at the end of it
+fn trailing_pad(line: &FormatLine) -> usize {
+    let mut pad = 0;
+    for segment in line.segments.iter().skip_while(|s| **s != Segment::Value).skip(1) {
+        match segment {
+            Segment::Command { name, arg } if name == "I" => pad += *arg as usize,
+            Segment::Command { name, .. } if name == "L" => break,
+            Segment::Text(_) => break,
+            _ => {}
+        }
+    }
+    pad
+}
+
+fn section_text(script: &LssScript, section: Section, group: u8) -> Vec<u8> {
+    script
+        .lines(section)
+        .filter(|l| l.group == group)
+        .flat_map(|l| l.text())
+        .collect()
+}
+
+enum Token {
+    Char(char),
+    Value(u8),
+}
+
+fn record_tokens(script: &LssScript, section: Section, group: u8) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for line in script.lines(section).filter(|l| l.group == group) {
+        for segment in &line.segments {
+            match segment {
+                Segment::Text(bytes) => tokens.extend(String::from_utf8_lossy(bytes).chars().map(Token::Char)),
+                Segment::Value => tokens.push(Token::Value(line.variable)),
+                Segment::Command { .. } => {}
+            }
+        }
+    }
+    tokens
+}
+
+// The record ends on the last character of the section; the first character
+// following a value separates the columns.
+fn record_delimiters(script: &LssScript, section: Section) -> Result<(char, char), LssError> {
+    let group = if matches!(section, Section::Result | Section::StartList) { GROUP_RESULT } else { GROUP_RESULTS_HEADER };
+    let tokens = record_tokens(script, section, group);
+
+    let terminator = match tokens.last() {
+        Some(Token::Char(c)) => *c,
+        _ => {
+            return Err(LssError::Layout {
+                section,
+                detail: "record does not end with a terminator character".to_string(),
+            });
+        }
+    };
+
+    let separator = tokens
+        .iter()
+        .skip_while(|t| !matches!(t, Token::Value(_)))
+        .find_map(|t| match t {
+            Token::Char(c) => Some(*c),
+            _ => None,
+        })
+        .filter(|c| *c != terminator)
+        .unwrap_or(',');
+
+    Ok((separator, terminator))
+}
+
+fn record_layout(
+    script: &LssScript,
+    section: Section,
+    group: u8,
+    separator: char,
+    terminator: char,
+) -> Result<RecordLayout, LssError> {
+    let mut layout = RecordLayout::default();
+    let mut current = Column::default();
+    let mut terminated = false;
+
+    for token in record_tokens(script, section, group) {
+        if terminated {
+            return Err(LssError::Layout {
+                section,
+                detail: format!("text after the '{}' terminator would start a bogus record", terminator),
+            });
+        }
+        match token {
+            Token::Value(var) => current.variables.push(var),
+            Token::Char(c) if c == separator => layout.columns.push(std::mem::take(&mut current)),
+            Token::Char(c) if c == terminator => {
+                layout.columns.push(std::mem::take(&mut current));
+                terminated = true;
+            }
+            Token::Char(_) => {}
+        }
+    }
+
+    if !current.variables.is_empty() {
+        return Err(LssError::Layout {
+            section,
+            detail: format!("last column is not closed by the '{}' terminator", terminator),
+        });
+    }
+
+    Ok(layout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_err(script: &str) -> LssError {
+        LssScript::parse(script).expect_err("script should be rejected")
+    }
+
+    fn decoder_err(script: &str) -> LssError {
+        let script = LssScript::parse(script).expect("script should parse");
+        LssDecoder::from_script(&script).expect_err("layout should be rejected")
+    }
+
+    #[test]
+    fn bundled_script_decodes() {
+        let script = LssScript::parse(BUNDLED_SCRIPT).unwrap();
+        assert!(script.has_section(Section::Result));
+        assert!(script.has_section(Section::ResultsHeader));
+
+        let decoder = LssDecoder::from_script(&script).unwrap();
+        assert_eq!((decoder.separator, decoder.terminator), (',', ';'));
+        assert_eq!(decoder.result.column_of(VAR_RESULT_PLACE), Some(0));
+        assert_eq!(decoder.result.column_of(VAR_RESULT_NAME), Some(3));
+        assert_eq!(decoder.header.column_of(VAR_HEADER_STATUS), Some(0));
+
+        let running = decoder.time_running.as_ref().unwrap();
+        assert!(!running.binary);
+        assert_eq!(decoder.time_stopped.as_ref().unwrap().prefix, b"\x01T\x02");
+        assert_eq!(decoder.wind.as_ref().unwrap().prefix, b"\x01W\x02");
+        assert_eq!(decoder.breaks.len(), 8);
+        assert_eq!(decoder.initialization, ["Command=LayoutDraw;Clear=2;"]);
+    }
+
+    #[test]
+    fn missing_file() {
+        let err = LssScript::load("/nonexistent/vmix.lss").unwrap_err();
+        assert!(matches!(err, LssError::Io { .. }));
+    }
+
+    #[test]
+    fn unknown_section() {
+        let err = parse_err(";;Results\n\\14\\01%s;\n");
+        assert!(matches!(err, LssError::UnknownSection { line: 1, ref name } if name == "Results"));
+    }
+
+    #[test]
+    fn line_before_section() {
+        assert!(matches!(parse_err("; comment\n\\14\\01%s;\n"), LssError::NoSection { line: 2 }));
+    }
+
+    #[test]
+    fn line_without_codes() {
+        assert!(matches!(parse_err(";;Result\n%s;\n"), LssError::MissingCodes { line: 2 }));
+    }
+
+    #[test]
+    fn group_outside_its_section() {
+        let err = parse_err(";;Wind\n\\14\\01%s;\n");
+        assert!(matches!(err, LssError::InvalidGroup { line: 2, section: Section::Wind, group: GROUP_RESULT }));
+    }
+
+    #[test]
+    fn unknown_variable() {
+        let err = parse_err(";;TimeRunning\n\\11\\03%s\n");
+        assert!(matches!(err, LssError::InvalidVariable { line: 2, group: GROUP_TIME, variable: 0x03 }));
+    }
+
+    #[test]
+    fn command_without_argument() {
+        assert!(matches!(parse_err(";;Result\n\\14\\01%s\\L\n"), LssError::BadEscape { line: 2, .. }));
+    }
+
+    #[test]
+    fn variable_without_specifier() {
+        assert!(matches!(parse_err(";;Result\n\\14\\01,\n"), LssError::MissingSpecifier { line: 2 }));
+    }
+
+    #[test]
+    fn two_specifiers() {
+        assert!(matches!(parse_err(";;Result\n\\14\\01%s%s,\n"), LssError::MultipleSpecifiers { line: 2 }));
+    }
+
+    #[test]
+    fn other_printf_specifier() {
+        let err = parse_err(";;Result\n\\14\\01%d,\n");
+        assert!(matches!(err, LssError::UnsupportedSpecifier { line: 2, spec: 'd' }));
+    }
+
+    #[test]
+    fn wind_without_leading_text() {
+        let err = decoder_err(";;Wind\n\\12\\01%s\n");
+        assert!(matches!(err, LssError::Layout { section: Section::Wind, .. }));
+    }
+
+    #[test]
+    fn stopped_time_without_leading_text() {
+        let err = decoder_err(";;TimeStopped\n\\11\\01%s\n");
+        assert!(matches!(err, LssError::Layout { section: Section::TimeStopped, .. }));
+    }
+
+    #[test]
+    fn gun_time_without_leading_text() {
+        let err = decoder_err(";;TimeGun\n\\11\\01%s\\03\\04\n");
+        assert!(matches!(err, LssError::Layout { section: Section::TimeGun, .. }));
+    }
+
+    #[test]
+    fn bare_running_time_followed_by_text() {
+        let err = decoder_err(";;TimeRunning\n\\11\\01%s\\03\\04\n");
+        assert!(matches!(err, LssError::Layout { section: Section::TimeRunning, .. }));
+    }
+}
@@ -1,7 +1,21 @@
 mod state;
+mod events;
+mod clock;
+mod config;
+mod lss;
+mod time;
 mod parser;
 mod tcp;
+mod udp;
 mod web;
+mod sse;
+mod vmix;
+mod vmix_tcp;
+mod titles;
+mod rules;
+mod pages;
+mod sources;
+mod channels;
 
 use std::sync::Arc;
 use tokio::runtime::Runtime;
@@ -10,7 +24,7 @@ use tray_icon::{
     TrayIconBuilder,
 };
 use tao::event_loop::{ControlFlow, EventLoop};
-use log::info;
+use log::{info, error};
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
@@ -45,23 +59,65 @@ fn main() {
         .unwrap();
 
     // Initialize State
-    let state = state::initialize_state();
+    let config = Arc::new(config::load_config());
+
+    // Load the scoreboard script Lynx is configured with. Decoding with any
+    // other layout would put the wrong fields on air, so a broken script keeps
+    // the Lynx listeners down and the error stays on the status page.
+    let decoder = match lss::load_decoder(lss::LSS_FILE) {
+        Ok(decoder) => {
+            info!("Loaded scoreboard script {}", lss::LSS_FILE);
+            Ok(Arc::new(decoder))
+        }
+        Err(e) => {
+            error!("Invalid scoreboard script {}: {}", lss::LSS_FILE, e);
+            error!("Not listening for FinishLynx until the script is fixed");
+            Err(format!("{}: {}", lss::LSS_FILE, e))
+        }
+    };
     
     // Create Tokio Runtime
     let rt = Runtime::new().expect("Failed to create Tokio runtime");
     
-    // Spawn Tasks
-    let state_tcp = state.clone();
+    // Spawn Tasks, one set per Lynx feed
+    let channel_configs = channels::channel_configs(&config);
+    if channel_configs.is_empty() {
+        error!("No usable channels in {}", config::CONFIG_FILE);
+        return;
+    }
+    let channels: Vec<channels::Channel> = channel_configs.iter()
+        .map(|channel| channels::Channel::start(rt.handle(), channel, decoder.as_ref().ok().cloned(), config.clone()))
+        .collect();
+    // vMix push and rules follow the first channel
+    let main_channel = channels[0].clone();
+    
+    // vMix title mapping, reloaded when the file changes
+    let (titles_tx, titles) = titles::channel();
+    rt.spawn(titles::watch_file(titles_tx));
+
+    // Rules fire vMix functions over the same connection
+    let (commands_tx, commands) = tokio::sync::mpsc::unbounded_channel();
+    rt.spawn(rules::run_rules(main_channel.state.clone(), config.clone(), &main_channel.bus, &main_channel.applied, commands_tx));
+
+    let state_vmix = main_channel.state.clone();
+    let config_vmix = config.clone();
+    let titles_vmix = titles.clone();
     rt.spawn(async move {
-        tcp::start_listener(state_tcp, 12345).await;
+        vmix_tcp::start_client(state_vmix, config_vmix, titles_vmix, commands).await;
     });
-    
-    let state_web = state.clone();
+
+    let ports: Vec<String> = channels.iter()
+        .map(|c| match &c.connect {
+            Some(addr) => format!("{} -> {}", c.name, addr),
+            None => format!("{} {}", c.name, c.port),
+        })
+        .collect();
+    let script_error = decoder.err();
     rt.spawn(async move {
-        web::start_server(state_web, 3000).await;
+        web::start_server(channels, config, script_error, 3000).await;
     });
 
-    info!("Application started. TCP: 12345, Web: 3000");
+    info!("Application started. TCP: {}, Web: 3000", ports.join(", "));
 
     // Run Event Loop
     let menu_channel = tray_icon::menu::MenuEvent::receiver();
@@ -0,0 +1,96 @@
+use crate::config::BridgeConfig;
+use crate::state::{ScoreboardState, SharedState, Topic};
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Results a page at a time for boards and lower thirds that only fit a few
+// rows. With "rotate" on, the bridge steps through the pages itself and
+// publishes the current one (the "page" topic, page[n] in the titles).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PagesConfig {
+    pub size: usize,
+    pub rotate: bool,
+    pub interval_ms: u64, // How long each page stays up
+}
+
+impl Default for PagesConfig {
+    fn default() -> Self {
+        Self {
+            size: 8,
+            rotate: false,
+            interval_ms: 5000,
+        }
+    }
+}
+
+// Always at least one page, even an empty one. Size 0 is everything at once.
+pub fn page_count(total: usize, size: usize) -> usize {
+    if size == 0 {
+        return 1;
+    }
+    total.div_ceil(size).max(1)
+}
+
+// Results shrink under us (a new race), so clamp rather than show nothing
+pub fn clamp_page(s: &ScoreboardState, page: usize, size: usize) -> usize {
+    page.min(page_count(s.results.len(), size) - 1)
+}
+
+// Index into s.results of the first row on the current page
+pub fn current_offset(s: &ScoreboardState) -> usize {
+    clamp_page(s, s.page, s.page_size) * s.page_size
+}
+
+// Pages are numbered from 1 here, rows too
+pub fn page_view(s: &ScoreboardState, page: usize, size: usize) -> Value {
+    let page = clamp_page(s, page, size);
+    let total = s.results.len();
+    let start = (page * size).min(total);
+    let end = if size == 0 { total } else { (start + size).min(total) };
+    let rows: Vec<Value> = s.results[start..end].iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let mut row = json!(r);
+            row["row"] = json!(start + i + 1);
+            row
+        })
+        .collect();
+    json!({
+        "page": page + 1,
+        "pages": page_count(total, size),
+        "page_size": size,
+        "total": total,
+        "results": rows,
+    })
+}
+
+pub async fn start_rotation(state: SharedState, config: Arc<BridgeConfig>) {
+    let pages = &config.pages;
+    state.write().page_size = pages.size;
+    if !pages.rotate {
+        return;
+    }
+
+    let mut race_key = String::new();
+    let mut interval = tokio::time::interval(Duration::from_millis(pages.interval_ms.max(500)));
+    interval.tick().await; // The first tick is immediate, let page 1 stay up
+    loop {
+        interval.tick().await;
+        let mut s = state.write();
+        // A new race starts from the top
+        let key = s.race_key();
+        let next = if key != race_key {
+            race_key = key;
+            0
+        } else {
+            (clamp_page(&s, s.page, s.page_size) + 1) % page_count(s.results.len(), s.page_size)
+        };
+        if next != s.page {
+            s.page = next;
+            s.notify(Topic::Page);
+        }
+    }
+}
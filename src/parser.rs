@@ -1,35 +1,28 @@
-use crate::state::SharedState;
-use log::debug;
-
-// Byte constants based on the LSS script provided (for binary fallback)
-const GROUP_INITIALIZE: u8 = 0x10;
-const GROUP_TIME: u8 = 0x11;
-const GROUP_WIND: u8 = 0x12;
-const GROUP_RESULTS_HEADER: u8 = 0x13;
-const GROUP_RESULT: u8 = 0x14;
-const GROUP_MESSAGE_HEADER: u8 = 0x15;
-const GROUP_MESSAGE: u8 = 0x16;
-const GROUP_BREAK_TIME: u8 = 0x17;
-const GROUP_BREAK_NAME: u8 = 0x18;
-
-// Field Variables
-const VAR_RESULT_PLACE: u8 = 0x01;
-const VAR_RESULT_LANE: u8 = 0x02;
-const VAR_RESULT_ID: u8 = 0x03;
-const VAR_RESULT_NAME: u8 = 0x04;
-const VAR_RESULT_AFFILIATION: u8 = 0x05;
-const VAR_RESULT_TIME: u8 = 0x06;
-const VAR_RESULT_DELTA: u8 = 0x07;
+use crate::lss::{self, LssDecoder};
+use crate::config::BridgeConfig;
+use crate::events::{EventBus, LynxEvent};
+use crate::sources::SourceHandle;
+use crate::time::{self, RaceTime};
+use crate::state::{parse_number, HeaderInfo, ResultStatus, StartType, WindReading};
+use indexmap::IndexMap;
+use log::{debug, warn};
+use std::sync::Arc;
 
 pub struct LynxParser {
-    state: SharedState,
+    bus: EventBus,
+    source: SourceHandle,
+    decoder: Arc<LssDecoder>,
+    config: Arc<BridgeConfig>,
     buffer: Vec<u8>,
 }
 
 impl LynxParser {
-    pub fn new(state: SharedState) -> Self {
+    pub fn new(bus: EventBus, source: SourceHandle, decoder: Arc<LssDecoder>, config: Arc<BridgeConfig>) -> Self {
         Self {
-            state,
+            bus,
+            source,
+            decoder,
+            config,
             buffer: Vec::new(),
         }
     }
@@ -39,10 +32,19 @@ impl LynxParser {
         
         if self.buffer.is_empty() { return; }
 
-        let first_byte = self.buffer[0];
+        // Running time framed by ;;TimeRunning, binary (\11\02) or text. Raw
+        // binary bytes can look like anything so check it first.
+        if let Some(frame) = self.decoder.time_running.clone()
+            && !frame.prefix.is_empty()
+            && self.find_sequence(&frame.prefix).is_some() {
+             self.process_running_frame(&frame);
+             return;
+        }
 
-        if first_byte >= 0x10 && first_byte <= 0x18 {
-             self.process_binary_lss();
+        // Gun time with text on both sides, ;;TimeGun in the script
+        if let Some(frame) = self.framed_gun()
+            && self.find_sequence(&frame.prefix).is_some() {
+             self.process_gun_frame(&frame);
              return;
         }
 
@@ -52,90 +54,295 @@ impl LynxParser {
             debug!("RAW BUFFER: {:?}", raw_str);
         }
 
-        // 2. Message Signature check (0x01 'M' 0x02)
-        // Log showed: [01, 4D, 02 ...]
-        if let Some(start_idx) = self.find_sequence(&[0x01, 0x4D, 0x02]) {
-             self.process_message_signature(start_idx);
-             return; 
+        // Message block, ;;MessageHeader in the script (0x01 'M' 0x02)
+        let message_header = self.decoder.message_header.clone();
+        if !message_header.is_empty()
+            && let Some(start_idx) = self.find_sequence(&message_header) {
+             self.process_message_signature(start_idx, message_header.len());
+             return;
         }
 
-        // 3. UTF-16LE CSV: Contains null bytes (0x00)
-        let has_null = self.buffer.iter().any(|&b| b == 0);
-        let has_semi = self.buffer.iter().any(|&b| b == b';');
-        let has_comma = self.buffer.iter().any(|&b| b == b',');
-        let has_time_chars = self.buffer.iter().any(|&b| b == b':' || b == b'.'); 
+        // Stopped time and time of day, ;;TimeStopped / ;;TimeOfDay in the script
+        // (0x01 'T' 0x02 ... 0x03 0x04)
+        let clock_prefixes: Vec<Vec<u8>> = [&self.decoder.time_stopped, &self.decoder.time_of_day]
+            .into_iter()
+            .flatten()
+            .map(|f| f.prefix.clone())
+            .filter(|p| !p.is_empty())
+            .collect();
+        if clock_prefixes.iter().any(|p| self.find_sequence(p).is_some()) {
+             self.process_clock_frame();
+             return;
+        }
 
-        if has_null {
+        // Wind, ;;Wind in the script
+        if let Some(frame) = self.decoder.wind.clone()
+            && !frame.prefix.is_empty()
+            && self.find_sequence(&frame.prefix).is_some() {
+             self.process_wind_frame(&frame);
+             return;
+        }
+
+        // Break times and names, \17\nn / \18\nn lines in the script
+        if self.decoder.breaks.iter().any(|b| self.find_sequence(&b.frame.prefix).is_some()) {
+             self.process_break_frame();
+             return;
+        }
+
+        // The script doesn't say how the text is encoded. Lynx sends either
+        // ASCII or UTF-16LE, and only the latter has null bytes.
+        if self.buffer.contains(&0) {
              self.process_csv_utf16();
+             return;
+        }
+
+        // Records end with the script's terminator, wait for it once a line has begun
+        let terminator = self.decoder.terminator as u32;
+        let separator = self.decoder.separator as u32;
+        if self.buffer.iter().any(|&b| b as u32 == terminator || b as u32 == separator) {
+             self.process_csv_ascii();
+        } else if self.decoder.time_running.as_ref().is_some_and(|f| f.prefix.is_empty())
+            || self.decoder.time_gun.is_some() {
+             // Bare running time, or the gun time after its leading text
+             self.process_ascii_time();
+        } else if self.buffer.len() > 100 {
+             self.buffer.clear();
+        }
+    }
+
+    fn process_running_frame(&mut self, frame: &lss::Frame) {
+        let Some(raw) = self.take_frame(frame) else { return };
+        if frame.binary {
+            if let Some(duration) = time::from_binary(&raw) {
+                let time_str = self.config.time_format.format(duration);
+                debug!("Parsed Binary Time: {}ms -> '{}'", duration.as_millis(), time_str);
+                self.publish(LynxEvent::ClockTick {
+                    time: time_str,
+                    ms: Some(duration.as_millis() as u64),
+                    precision: self.config.time_format.precision,
+                });
+            }
         } else {
-             // ASCII handling
-             if has_semi || has_comma {
-                 self.process_csv_ascii();
-             } else if has_time_chars {
-                 self.process_ascii_time();
-             } else {
-                 if self.buffer.len() > 100 {
-                     self.buffer.clear();
-                 }
-             }
+            let time_str = String::from_utf8_lossy(&raw).trim().to_string();
+            if !time_str.is_empty() {
+                debug!("Parsed Framed Time: '{}'", time_str);
+                self.publish_clock_tick(time_str);
+            }
+        }
+
+        if !self.buffer.is_empty() {
+            self.process_chunk(&[]);
+        }
+    }
+
+    // The gun time only needs its own frame when something follows the value,
+    // "*start10:00:00.0" is picked out of the text by process_ascii_time
+    fn framed_gun(&self) -> Option<lss::Frame> {
+        self.decoder.time_gun.clone().filter(|f| f.binary || !f.suffix.is_empty())
+    }
+
+    fn process_gun_frame(&mut self, frame: &lss::Frame) {
+        let Some(raw) = self.take_frame(frame) else { return };
+        let (time_str, ms) = self.frame_time(frame, &raw);
+        if !time_str.is_empty() {
+            debug!("Parsed Gun Time: '{}'", time_str);
+            self.publish(LynxEvent::GunStart { time: time_str, ms });
+        }
+
+        if !self.buffer.is_empty() {
+            self.process_chunk(&[]);
+        }
+    }
+
+    // Text as sent, or binary milliseconds rendered with our own time format
+    fn frame_time(&self, frame: &lss::Frame, raw: &[u8]) -> (String, Option<u64>) {
+        if frame.binary {
+            match time::from_binary(raw) {
+                Some(duration) => (self.config.time_format.format(duration), Some(duration.as_millis() as u64)),
+                None => (String::new(), None),
+            }
+        } else {
+            let text = String::from_utf8_lossy(raw).trim().to_string();
+            let ms = RaceTime::parse(&text).and_then(|t| t.ms);
+            (text, ms)
+        }
+    }
+
+    fn process_clock_frame(&mut self) {
+        let stopped = self.decoder.time_stopped.as_ref().and_then(|f| f.find(&self.buffer).map(|m| (f, m)));
+        let time_of_day = self.decoder.time_of_day.as_ref().and_then(|f| f.find(&self.buffer).map(|m| (f, m)));
+
+        // Take whichever frame comes first. When both share framing, the
+        // time of day is the one padded out by its \I command.
+        let (is_time_of_day, frame) = match (stopped, time_of_day) {
+            (Some((_, s)), Some((tod, t))) if s == t => {
+                let raw = &self.buffer[s.1];
+                let padded = tod.trailing_pad > 0
+                    && raw.len() >= tod.trailing_pad
+                    && raw[raw.len() - tod.trailing_pad..].iter().all(|&b| b == b' ');
+                (padded, tod.clone())
+            }
+            (Some((f, s)), Some((tod, t))) => if t.0 < s.0 { (true, tod.clone()) } else { (false, f.clone()) },
+            (Some((f, _)), None) => (false, f.clone()),
+            (None, Some((tod, _))) => (true, tod.clone()),
+            (None, None) => {
+                self.wait_for_frame();
+                return;
+            }
+        };
+
+        let Some(raw) = self.take_frame(&frame) else { return };
+        let (time_str, time_ms) = self.frame_time(&frame, &raw);
+
+        if is_time_of_day {
+            debug!("Parsed Time Of Day: '{}'", time_str);
+            self.publish(LynxEvent::TimeOfDay { time: time_str });
+        } else {
+            debug!("Parsed Stopped Time: '{}'", time_str);
+            self.publish(LynxEvent::TimeStopped { time: time_str, ms: time_ms });
+        }
+
+        // The F1 trigger usually follows in the same chunk
+        if !self.buffer.is_empty() {
+            self.process_chunk(&[]);
+        }
+    }
+
+    // Pulls a complete frame out of the buffer and returns its raw value
+    fn take_frame(&mut self, frame: &lss::Frame) -> Option<Vec<u8>> {
+        let Some((start, value, end)) = frame.find(&self.buffer) else {
+            self.wait_for_frame();
+            return None;
+        };
+        self.parse_leading(start);
+        let raw = self.buffer[value.start - start..value.end - start].to_vec();
+        self.buffer.drain(0..end - start);
+        Some(raw)
+    }
+
+    // Wait for the rest of the frame, unless it is clearly never coming
+    fn wait_for_frame(&mut self) {
+        if self.buffer.len() > 256 {
+            self.buffer.clear();
+        }
+    }
+
+    // Whatever came in front of a frame (a result line, another frame) was
+    // sent first, so it gets parsed first. Leaves the frame at the start.
+    fn parse_leading(&mut self, start: usize) {
+        if start == 0 {
+            return;
+        }
+        let rest = self.buffer.split_off(start);
+        self.process_chunk(&[]);
+        if !self.buffer.is_empty() {
+            debug!("Dropping {} bytes of an unfinished line before a frame", self.buffer.len());
+        }
+        self.buffer = rest;
+    }
+
+    fn process_wind_frame(&mut self, frame: &lss::Frame) {
+        let Some(raw) = self.take_frame(frame) else { return };
+
+        let reading = if frame.binary {
+            let hundredths = i32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]);
+            Some(WindReading::from_hundredths(hundredths))
+        } else {
+            WindReading::parse(&String::from_utf8_lossy(&raw))
+        };
+
+        match reading {
+            Some(reading) => self.publish(LynxEvent::WindMeasured { reading }),
+            None => debug!("Ignoring wind value {:?}", String::from_utf8_lossy(&raw)),
+        }
+
+        if !self.buffer.is_empty() {
+            self.process_chunk(&[]);
+        }
+    }
+
+    fn process_break_frame(&mut self) {
+        // Several break lines can share a chunk, take the earliest
+        let next = self.decoder.breaks.iter()
+            .filter_map(|b| self.find_sequence(&b.frame.prefix).map(|idx| (idx, b.clone())))
+            .min_by_key(|(idx, _)| *idx);
+        let Some((_, brk)) = next else { return };
+        let Some(raw) = self.take_frame(&brk.frame) else { return };
+
+        let value = String::from_utf8_lossy(&raw).trim().to_string();
+        debug!("Parsed Break {} {}: '{}'", brk.index, if brk.is_name { "name" } else { "time" }, value);
+        let (name, time) = if brk.is_name { (Some(value), None) } else { (None, Some(value)) };
+        self.publish(LynxEvent::SplitUpdated { index: brk.index, name, time });
+
+        if !self.buffer.is_empty() {
+            self.process_chunk(&[]);
         }
     }
 
     fn process_ascii_time(&mut self) {
         // "  12:16:03.0  "
-        // Or "*start12:16:03.0"
+        // Or "*start12:16:03.0" (prefix taken from ;;TimeGun)
         if let Ok(s) = String::from_utf8(self.buffer.clone()) {
             let parts: Vec<&str> = s.split_whitespace().collect();
+            let gun_prefix = self.decoder.time_gun.as_ref()
+                .filter(|_| self.framed_gun().is_none())
+                .map(|f| String::from_utf8_lossy(&f.prefix).trim().to_string())
+                .filter(|p| !p.is_empty());
+            let is_gun = |p: &str| gun_prefix.as_deref().is_some_and(|prefix| p.contains(prefix));
             
             // Check for Gun Start
-            let gun_start_part = parts.iter().find(|p| p.contains("*start"));
-            if let Some(part) = gun_start_part {
+            let gun_start_part = parts.iter().find(|p| is_gun(p));
+            let mut found = gun_start_part.is_some();
+            if let (Some(part), Some(prefix)) = (gun_start_part, gun_prefix.as_deref()) {
                 // "*start10:00:00.0" -> extract "10:00:00.0"
-                let time_str = part.replace("*start", "");
+                let time_str = part.replace(prefix, "");
                 if !time_str.is_empty() {
                     debug!("Parsed Gun Time: '{}'", time_str);
-                    let mut state = self.state.write();
-                    state.gun_time = time_str.clone();
-                    // Also update history if we have an event number
-                     if !state.event_number.is_empty() {
-                         let key = state.event_number.clone();
-                         state.races.entry(key)
-                             .and_modify(|race| race.gun_time = time_str.clone());
-                     }
+                    let ms = RaceTime::parse(&time_str).and_then(|t| t.ms);
+                    self.publish(LynxEvent::GunStart { time: time_str, ms });
                 }
             }
 
-            // Standard Running Time
+            // Standard Running Time, when ;;TimeRunning sends it bare
             // Find the last part that looks like a time (contains ':' or '.') AND isn't the gun start packet
-            let last_valid_time = parts.iter().rfind(|p| {
+            let bare_running = self.decoder.time_running.as_ref().is_some_and(|f| f.prefix.is_empty());
+            let last_valid_time = parts.iter().filter(|_| bare_running).rfind(|p| {
                 let is_simple = p.chars().all(|c| c.is_ascii_digit() || c == ':' || c == '.');
                 let has_digit = p.chars().any(|c| c.is_ascii_digit());
                 let has_sep = p.contains(':') || p.contains('.');
-                is_simple && has_digit && has_sep && !p.contains("*start")
+                is_simple && has_digit && has_sep && !is_gun(p)
             });
             
             if let Some(time_str) = last_valid_time {
                  debug!("Parsed ASCII Time: '{}'", time_str);
-                 let mut state = self.state.write();
-                 state.time = time_str.to_string();
-                 state.running = true;
+                 self.publish_clock_tick(time_str.to_string());
+                 found = true;
+            }
+            // Anything else may be the start of a frame, give it time to arrive
+            if found || self.buffer.len() > 100 {
+                self.buffer.clear();
             }
-            // Clear buffer after processing
+        } else if self.buffer.len() > 100 {
             self.buffer.clear();
         }
     }
     
     fn process_csv_ascii(&mut self) {
         if let Ok(s) = String::from_utf8(self.buffer.clone()) {
-             // Process up to last semicolon
-             if let Some(last_semi) = s.rfind(';') {
-                 let (processed_str, _) = s.split_at(last_semi + 1);
+             // Process up to last terminator
+             let terminator = self.decoder.terminator;
+             if let Some(last_semi) = s.rfind(terminator) {
+                 let (processed_str, _) = s.split_at(last_semi + terminator.len_utf8());
                  self.parse_csv_string(processed_str);
                  
                  // Remove processed bytes
-                 // Since ASCII is 1 byte per char, index matches
-                 let bytes_to_remove = last_semi + 1;
+                 // Since the string is the buffer as-is, index matches
+                 let bytes_to_remove = last_semi + terminator.len_utf8();
                  self.buffer.drain(0..bytes_to_remove);
+
+                 // Command lines end in \0a; a leftover byte would misalign UTF-16 that follows
+                 let line_ends = self.buffer.iter().take_while(|&&b| b == b'\r' || b == b'\n').count();
+                 self.buffer.drain(0..line_ends);
              }
         }
     }
@@ -156,15 +363,16 @@ impl LynxParser {
             
         let full_text = String::from_utf16_lossy(&u16_vec);
         
-        if let Some(last_semi) = full_text.rfind(';') {
-            let (processed_str, _) = full_text.split_at(last_semi + 1);
+        let terminator = self.decoder.terminator;
+        if let Some(last_semi) = full_text.rfind(terminator) {
+            let (processed_str, _) = full_text.split_at(last_semi + terminator.len_utf8());
             
             self.parse_csv_string(processed_str);
             
             // Calculate bytes to remove
              let mut cut_index = 0;
             for (i, &c) in u16_vec.iter().enumerate().rev() {
-                if c == 0x003B { // ';'
+                if c as u32 == terminator as u32 {
                     cut_index = i + 1;
                     break;
                 }
@@ -183,12 +391,28 @@ impl LynxParser {
     }
 
     fn parse_csv_string(&mut self, text: &str) {
-        let records: Vec<&str> = text.split(';').collect();
+        let decoder = self.decoder.clone();
 
-        if text.contains("Command=LayoutDraw") {
+        // ;;Initialization commands (Command=LayoutDraw;Clear=2;)
+        if decoder.initialization.iter().any(|cmd| text.contains(cmd.as_str())) {
             debug!("LayoutDraw command detected. Clearing results.");
-            self.state.write().results.clear();
+            self.publish(LynxEvent::ResultsCleared);
+        }
+
+        // Command= statements run to the end of their line, the rest is CSV
+        let mut csv = String::with_capacity(text.len());
+        for line in text.split_inclusive('\n') {
+            let Some(start) = line.find("Command=") else {
+                csv.push_str(line);
+                continue;
+            };
+            csv.push_str(&line[..start]);
+            if let Some((command, params)) = parse_command(&line[start..]) {
+                debug!("Command: {} {:?}", command, params);
+                self.publish(LynxEvent::Command { command, params });
+            }
         }
+        let records: Vec<&str> = csv.split(decoder.terminator).collect();
 
         for record in records {
             let raw_clean = record.trim();
@@ -197,231 +421,388 @@ impl LynxParser {
             let clean = Self::sanitize(raw_clean);
             if clean.is_empty() { continue; }
             
+            let fields: Vec<&str> = clean.split(decoder.separator).collect();
+            let fields_clean: Vec<String> = fields.iter().map(|s| Self::sanitize(s.trim())).collect();
+            
+            if fields_clean.is_empty() { continue; }
+
             // "UNOFFICIAL,15B Group 1 1500m 111m,nwi,15B,1,01,15B-1-01,AUTO,7"
-            if clean.starts_with("OFFICIAL") || clean.starts_with("UNOFFICIAL") {
+            let header_field = |var: u8| {
+                decoder.header.column_of(var)
+                    .and_then(|idx| fields_clean.get(idx))
+                    .cloned()
+                    .unwrap_or_default()
+            };
+            let status = ResultStatus::parse(&header_field(lss::VAR_HEADER_STATUS));
+            if status.is_some() {
                 debug!("Detected Header: {}", clean);
-                let mut s = self.state.write();
-                s.results.clear(); // Start of new list
-                s.header = clean.to_string(); // Store header
-                
-                // Parse header fields
-                let params: Vec<&str> = clean.split(',').collect();
-                // 0: Status (UNOFFICIAL)
-                // 1: Event Name
-                // 2: Wind?
-                // 3: Event Number (Race Number)
-                // 4: Round
-                // 5: Heat
                 
-                if params.len() > 3 {
-                    let evt_name = params[1].trim().to_string();
-                    let evt_num = params[3].trim().to_string();
-                    debug!("Parsed Event Info - Name: '{}', Num: '{}'", evt_name, evt_num);
-                    s.event_name = evt_name;
-                    s.event_number = evt_num;
-                }
+                // Column positions come from ;;ResultsHeader
+                let evt_name = header_field(lss::VAR_HEADER_EVENT_NAME);
+                let evt_num = header_field(lss::VAR_HEADER_EVENT_NUMBER);
+                debug!("Parsed Event Info - Name: '{}', Num: '{}'", evt_name, evt_num);
+                let info = HeaderInfo {
+                    status,
+                    wind: header_field(lss::VAR_HEADER_WIND),
+                    round: parse_number(&header_field(lss::VAR_HEADER_ROUND)),
+                    heat: parse_number(&header_field(lss::VAR_HEADER_HEAT)),
+                    start_type: StartType::parse(&header_field(lss::VAR_HEADER_START_TYPE)),
+                    participants: parse_number(&header_field(lss::VAR_HEADER_PARTICIPANTS)),
+                    race_id: decoder.header.combined_column()
+                        .and_then(|idx| fields_clean.get(idx))
+                        .cloned()
+                        .unwrap_or_default(),
+                };
+                debug!("Parsed Header Info: {:?}", info);
+                self.publish(LynxEvent::HeaderReceived {
+                    header: clean.to_string(),
+                    event_name: evt_name,
+                    event_number: evt_num,
+                    info,
+                });
                 
                 continue;
             }
-            
-            // Fields: Place, Lane, ID, Name, Affiliation, Time
-            let fields: Vec<&str> = clean.split(',').collect();
-            let fields_clean: Vec<String> = fields.iter().map(|s| Self::sanitize(s.trim())).collect();
-            
-            if fields_clean.is_empty() { continue; }
+
+            let result_field = |var: u8| {
+                decoder.result.column_of(var)
+                    .and_then(|idx| fields_clean.get(idx))
+                    .map(String::as_str)
+                    .unwrap_or_default()
+            };
 
             // Result Heuristic: 
-            // 1. Starts with Number (Place)
+            // 1. Has a numeric Place
             // 2. OR Has valid Lane OR Has valid ID
             let could_be_result = fields_clean.len() >= 4 && (
-                fields_clean[0].parse::<u32>().is_ok() || 
-                !fields_clean[1].is_empty() || 
-                !fields_clean[2].is_empty()
+                result_field(lss::VAR_RESULT_PLACE).parse::<u32>().is_ok() ||
+                !result_field(lss::VAR_RESULT_LANE).is_empty() ||
+                !result_field(lss::VAR_RESULT_ID).is_empty()
             );
             
             if could_be_result {
+                 if fields_clean.len() > decoder.result.columns.len() {
+                     warn!(
+                         "Result has {} columns but ;;Result in the script defines {}: {:?}",
+                         fields_clean.len(), decoder.result.columns.len(), fields_clean
+                     );
+                 }
+
+                 // Map columns as laid out by ;;Result
                  let mut res = crate::state::AthleteResult::default();
-                 
-                 // Map based on observed CSV:
-                 // 0: Place
-                 // 1: Lane
-                 // 2: ID
-                 // 3: Name
-                 // 4: Affiliation
-                 // 5: Time
-                 
-                 if fields_clean.len() > 0 { res.place = fields_clean[0].clone(); }
-                 if fields_clean.len() > 1 { res.lane = fields_clean[1].clone(); }
-                 if fields_clean.len() > 2 { res.id = fields_clean[2].clone(); }
-                 if fields_clean.len() > 3 { res.name = fields_clean[3].clone(); }
-                 if fields_clean.len() > 4 { res.affiliation = fields_clean[4].clone(); }
-                 if fields_clean.len() > 5 { res.time = fields_clean[5].clone(); }
-                 
-                 debug!("Parsed Athlete: {} (Place: {}, Time: {})", res.name, res.place, res.time);
-                 
-                 let mut s = self.state.write();
-                 
-                 debug!("Result Candidate: {:?}", fields_clean);
+                 for (idx, value) in fields_clean.iter().enumerate() {
+                     let Some(var) = decoder.result.variable_at(idx) else { continue };
+                     let value = value.clone();
+                     match var {
+                         lss::VAR_RESULT_PLACE => res.place = value,
+                         lss::VAR_RESULT_LANE => res.lane = value,
+                         lss::VAR_RESULT_ID => res.id = value,
+                         lss::VAR_RESULT_NAME => res.name = value,
+                         lss::VAR_RESULT_AFFILIATION => res.affiliation = value,
+                         lss::VAR_RESULT_TIME => res.time = value,
+                         lss::VAR_RESULT_DELTA => res.delta_time = value,
+                         lss::VAR_RESULT_CUMULATIVE_SPLIT => res.cumulative_split = value,
+                         lss::VAR_RESULT_LAST_SPLIT => res.last_split = value,
+                         lss::VAR_RESULT_LAPS_TO_GO => res.laps_to_go = value,
+                         lss::VAR_RESULT_LICENSE => res.license = value,
+                         lss::VAR_RESULT_REACTION_TIME => res.reaction_time = value,
+                         lss::VAR_RESULT_SPEED => res.speed = value,
+                         lss::VAR_RESULT_PACE => res.pace = value,
+                         lss::VAR_RESULT_BEST_SPLIT => res.best_split = value,
+                         _ => {}
+                     }
+                 }
 
-                 // Upsert into Live Results
-                 // Try to match by Lane first, then Place, then ID
-                 let existing_idx = s.results.iter().position(|r| {
-                     (!res.lane.is_empty() && r.lane == res.lane) ||
-                     (res.lane.is_empty() && !res.place.is_empty() && r.place == res.place) ||
-                     (res.lane.is_empty() && res.place.is_empty() && !res.id.is_empty() && r.id == res.id)
-                 });
-
-                 if let Some(idx) = existing_idx {
-                     s.results[idx] = res.clone();
-                 } else {
-                     s.results.push(res.clone());
+                 res.time_value = RaceTime::parse(&res.time);
+                 res.delta_value = RaceTime::parse(&res.delta_time);
+                 if let Some(format) = &self.config.result_time_format {
+                     if let Some(t) = &res.time_value {
+                         res.time = t.format(format);
+                     }
+                     if let Some(t) = &res.delta_value {
+                         let delta = t.format(format);
+                         res.delta_time = if t.ms.is_some() && t.raw.trim().starts_with('+') { format!("+{}", delta) } else { delta };
+                     }
                  }
                  
-                 // Sort Live Results
-                 crate::state::sort_results(&mut s.results);
-                 
-                     // Update History
-                 if !s.event_number.is_empty() {
-                     let key = s.event_number.clone();
-                     let evt_name = s.event_name.clone();
-                     let current_gun_time = s.gun_time.clone();
-                     let current_header = s.header.clone();
-                     
-                     s.races.entry(key.clone())
-                         .and_modify(|race| {
-                             race.header = current_header.clone(); // Update header
-                             let hist_idx = race.results.iter().position(|r| {
-                                 (!res.lane.is_empty() && r.lane == res.lane) ||
-                                 (res.lane.is_empty() && !res.place.is_empty() && r.place == res.place) ||
-                                 (res.lane.is_empty() && res.place.is_empty() && !res.id.is_empty() && r.id == res.id)
-                             });
-                             
-                             if let Some(idx) = hist_idx {
-                                 race.results[idx] = res.clone();
-                             } else {
-                                 race.results.push(res.clone());
-                             }
-                             // Sort History Results
-                             crate::state::sort_results(&mut race.results);
-                         })
-                         .or_insert_with(|| {
-                             // Initial sort logic if needed (vec![res] is already sorted)
-                             crate::state::RaceData {
-                                 event_name: evt_name,
-                                 event_number: key,
-                                 gun_time: current_gun_time,
-                                 header: current_header,
-                                 results: vec![res],
-                             }
-                         });
-                         
-                     // Persist changes
-                     crate::state::save_history(&s.races);
-                 }
+                 debug!("Parsed Athlete: {} (Place: {}, Time: {})", res.name, res.place, res.time);
+                 debug!("Result Candidate: {:?}", fields_clean);
+                 self.publish(LynxEvent::ResultUpserted { result: Box::new(res) });
             }
         }
     }
 
-    fn process_binary_lss(&mut self) {
-        // Legacy fallback
-        loop {
-            if self.buffer.is_empty() { break; }
-            let group_code = self.buffer[0];
-            let mut consumed = 0;
-
-            match group_code {
-                GROUP_TIME => {
-                    if let Some(end_idx) = self.find_sequence(&[0x03, 0x04]) {
-                        if let Some(start_idx) = self.find_sequence(&[0x02]) {
-                             if start_idx < end_idx {
-                                 let time_bytes = &self.buffer[start_idx+1..end_idx];
-                                 let time_str = String::from_utf8_lossy(time_bytes).trim().to_string();
-                                 debug!("Recognized LSS TIME packet: '{}'", time_str);
-                                 let mut s = self.state.write();
-                                 s.time = time_str;
-                                 s.running = true;
-                             }
-                        }
-                        consumed = end_idx + 2;
-                    }
-                },
-                GROUP_MESSAGE_HEADER => {
-                    // Header: \15\00\01M\02
-                    // Trailer: \15\00\03\04
-                    // We can just detect \15 and try to find the end byte.
-                    // For header, end is \02? For trailer \04?
-                    // Simplified: just consume until \02 or \04.
-                    
-                    if let Some(end_idx) = self.find_sequence(&[0x02]) {
-                         debug!("Detected LSS Message Header. Clearing old messages.");
-                         self.state.write().messages.clear();
-                         consumed = end_idx + 1;
-                    } else if let Some(end_idx) = self.find_sequence(&[0x03, 0x04]) {
-                         // Trailer
-                         consumed = end_idx + 2;
-                    }
-                },
-                GROUP_MESSAGE => {
-                    // \16 \01 [Text] \05
-                    if let Some(end_idx) = self.find_sequence(&[0x05]) {
-                         // Check for \01 at start of value
-                         // buffer[0] is 0x16. buffer[1] should be 0x01.
-                         if self.buffer.len() > 2 && self.buffer[1] == 0x01 {
-                             let val_bytes = &self.buffer[2..end_idx];
-                             let val = String::from_utf8_lossy(val_bytes).to_string(); // Don't trim to preserve spacing if needed?
-                             debug!("Parsed LSS Message: '{}'", val);
-                             self.state.write().messages.push(val);
-                         }
-                         consumed = end_idx + 1;
-                    }
-                },
-                _ => { consumed = 1; }
-            }
-            if consumed > 0 { self.buffer.drain(0..consumed); } else { break; }
+    // Every source is parsed, but only the active one reaches the scoreboard
+    fn publish(&self, event: LynxEvent) {
+        // Failover goes by whether a source is still sending time
+        let time_packet = matches!(event,
+            LynxEvent::ClockTick { .. } | LynxEvent::TimeStopped { .. } | LynxEvent::TimeOfDay { .. } | LynxEvent::GunStart { .. });
+        self.source.record_frame(time_packet);
+        if self.source.is_active() {
+            self.bus.publish(event);
+        }
+    }
+
+    // Forget a half-received frame, e.g. when datagrams aren't stitched together
+    pub fn discard_partial(&mut self) {
+        if !self.buffer.is_empty() {
+            debug!("Dropping {} unparsed bytes", self.buffer.len());
+            self.buffer.clear();
         }
     }
 
-    fn process_message_signature(&mut self, start_idx: usize) {
-        let header_end = start_idx + 3;
+    pub fn source(&self) -> &SourceHandle {
+        &self.source
+    }
+
+    fn publish_clock_tick(&self, time: String) {
+        let parsed = RaceTime::parse(&time);
+        let precision = parsed.as_ref().map_or(0, |t| t.precision);
+        self.publish(LynxEvent::ClockTick { time, ms: parsed.and_then(|t| t.ms), precision });
+    }
+
+    fn process_message_signature(&mut self, start_idx: usize, header_len: usize) {
+        let header_end = start_idx + header_len;
+        let trailer = self.decoder.message_trailer.clone();
+        if trailer.is_empty() { return; }
         
         // Trailer: \03\04
-        if let Some(trailer_idx) = self.find_sequence(&[0x03, 0x04]) {
-             if trailer_idx >= header_end {
-                 debug!("Detected Message Signature. Clearing old messages.");
-                 self.state.write().messages.clear();
-                 
-                 let content_slice = &self.buffer[header_end..trailer_idx];
-                 let content_len = content_slice.len();
-                 
-                 if content_len > 0 {
-                     let text_slice = if content_slice[content_len - 1] == 0x05 {
-                         &content_slice[..content_len - 1]
-                     } else {
-                         content_slice
-                     };
-                     
-                     if !text_slice.is_empty() {
-                         let msg = String::from_utf8_lossy(text_slice).to_string();
-                         debug!("Parsed Message Text: '{}'", msg);
-                         self.state.write().messages.push(msg);
+        let trailer_idx = self.buffer[header_end..]
+            .windows(trailer.len())
+            .position(|window| window == trailer.as_slice())
+            .map(|idx| idx + header_end);
+
+        if let Some(trailer_idx) = trailer_idx {
+             self.parse_leading(start_idx);
+             let (header_end, trailer_idx) = (header_end - start_idx, trailer_idx - start_idx);
+
+             debug!("Detected Message Signature. Clearing old messages.");
+             let mut messages = Vec::new();
+
+             // Each ;;Message line ends with its suffix (\05)
+             let content_slice = &self.buffer[header_end..trailer_idx];
+             let line_suffix = self.decoder.message_line.as_ref()
+                 .map(|f| f.suffix.clone())
+                 .unwrap_or_default();
+             let lines: Vec<&[u8]> = match line_suffix.as_slice() {
+                 [] => vec![content_slice],
+                 [single] => content_slice.split(|b| b == single).collect(),
+                 suffix => {
+                     let mut lines = Vec::new();
+                     let mut rest = content_slice;
+                     while let Some(idx) = rest.windows(suffix.len()).position(|w| w == suffix) {
+                         lines.push(&rest[..idx]);
+                         rest = &rest[idx + suffix.len()..];
                      }
+                     lines.push(rest);
+                     lines
+                 }
+             };
+
+             for text_slice in lines {
+                 if !text_slice.is_empty() {
+                     let msg = String::from_utf8_lossy(text_slice).to_string();
+                     debug!("Parsed Message Text: '{}'", msg);
+                     messages.push(msg);
                  }
-                 
-                 let consumed = trailer_idx + 2;
-                 self.buffer.drain(0..consumed);
              }
+             self.publish(LynxEvent::MessageSet { messages });
+             
+             let consumed = trailer_idx + trailer.len();
+             self.buffer.drain(0..consumed);
         }
     }
 
     fn find_sequence(&self, seq: &[u8]) -> Option<usize> {
         self.buffer.windows(seq.len()).position(|window| window == seq)
     }
-    
-    fn handle_result_field(&mut self, _var_code: u8, _value: String) {
-        // Legacy
+}
+
+// "Command=Trigger;Name=F1;Action=On;" -> ("Trigger", {Name: F1, Action: On})
+fn parse_command(statement: &str) -> Option<(String, IndexMap<String, String>)> {
+    let mut command = None;
+    let mut params = IndexMap::new();
+    for pair in statement.split(';') {
+        let pair = LynxParser::sanitize(pair);
+        let Some((key, value)) = pair.split_once('=') else { continue };
+        let (key, value) = (key.trim(), value.trim());
+        if key == "Command" && command.is_none() {
+            command = Some(value.to_string());
+        } else if !key.is_empty() {
+            params.insert(key.to_string(), value.to_string());
+        }
     }
+    command.filter(|c| !c.is_empty()).map(|c| (c, params))
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Origin;
+    use crate::lss::LssScript;
+    use crate::sources::SourceRegistry;
+    use tokio::sync::broadcast;
+
+    // Running time as raw milliseconds, results as plain ASCII CSV
+    const BINARY_SCRIPT: &str = r";;TimeRunning
+\11\02\01R\02%s\03\04
+;;Result
+\14\01%s,
+\14\02%s,
+\14\04%s,
+\14\06%s;
+";
+
+    // Text frames that may share a chunk with result lines
+    const FRAMED_SCRIPT: &str = r";;TimeStopped
+\11\01\01T\02%s\03\04
+;;Wind
+\12\01\01W\02%s\03\04
+;;TimeBreak
+\17\01\01S\0201T%s\03\04
+;;Result
+\14\01%s,
+\14\02%s,
+\14\04%s,
+\14\06%s;
+";
+
+    fn parser(script: &str) -> (LynxParser, broadcast::Receiver<(Origin, LynxEvent)>) {
+        let script = LssScript::parse(script).unwrap();
+        with_decoder(LssDecoder::from_script(&script).unwrap())
+    }
+
+    fn with_decoder(decoder: LssDecoder) -> (LynxParser, broadcast::Receiver<(Origin, LynxEvent)>) {
+        let bus = EventBus::default();
+        let rx = bus.subscribe();
+        let source = SourceRegistry::default().register("test", "127.0.0.1:12345".parse().unwrap());
+        (LynxParser::new(bus, source, Arc::new(decoder), Arc::default()), rx)
+    }
+
+    fn events(rx: &mut broadcast::Receiver<(Origin, LynxEvent)>) -> Vec<LynxEvent> {
+        std::iter::from_fn(|| rx.try_recv().ok().map(|(_, event)| event)).collect()
+    }
 
+    fn binary_time(ms: u32) -> Vec<u8> {
+        let mut frame = b"\x01R\x02".to_vec();
+        frame.extend(ms.to_le_bytes());
+        frame.extend(b"\x03\x04");
+        frame
+    }
+
+    #[test]
+    fn result_line_before_binary_time() {
+        let (mut parser, mut rx) = parser(BINARY_SCRIPT);
+        let mut chunk = b"1,3,Heath KENNETT,2:27.93;".to_vec();
+        chunk.extend(binary_time(12_340));
+        parser.process_chunk(&chunk);
+
+        let events = events(&mut rx);
+        assert_eq!(events.len(), 2, "{:?}", events);
+        assert!(matches!(&events[0], LynxEvent::ResultUpserted { result } if result.name == "Heath KENNETT"));
+        assert!(matches!(&events[1], LynxEvent::ClockTick { ms: Some(12_340), .. }));
+    }
+
+    #[test]
+    fn result_line_after_binary_time() {
+        let (mut parser, mut rx) = parser(BINARY_SCRIPT);
+        let mut chunk = binary_time(500);
+        chunk.extend(b"2,5,Kim LEE,2:28.10;");
+        parser.process_chunk(&chunk);
+
+        let events = events(&mut rx);
+        assert_eq!(events.len(), 2, "{:?}", events);
+        assert!(matches!(&events[0], LynxEvent::ClockTick { ms: Some(500), .. }));
+        assert!(matches!(&events[1], LynxEvent::ResultUpserted { result } if result.place == "2"));
+    }
+
+    #[test]
+    fn stopped_time_between_result_lines() {
+        let (mut parser, mut rx) = parser(FRAMED_SCRIPT);
+        parser.process_chunk(b"1,3,Heath KENNETT,2:27.93;\x01T\x022:27.93\x03\x042,5,Kim LEE,2:28.10;");
+
+        let events = events(&mut rx);
+        assert_eq!(events.len(), 3, "{:?}", events);
+        assert!(matches!(&events[0], LynxEvent::ResultUpserted { result } if result.place == "1"));
+        assert!(matches!(&events[1], LynxEvent::TimeStopped { time, .. } if time == "2:27.93"));
+        assert!(matches!(&events[2], LynxEvent::ResultUpserted { result } if result.place == "2"));
+    }
 
+    #[test]
+    fn wind_before_result_line() {
+        let (mut parser, mut rx) = parser(FRAMED_SCRIPT);
+        parser.process_chunk(b"\x01W\x02+1.2\x03\x041,3,Heath KENNETT,10.12;");
 
+        let events = events(&mut rx);
+        assert_eq!(events.len(), 2, "{:?}", events);
+        assert!(matches!(&events[0], LynxEvent::WindMeasured { .. }));
+        assert!(matches!(&events[1], LynxEvent::ResultUpserted { result } if result.time == "10.12"));
+    }
+
+    #[test]
+    fn split_after_result_line() {
+        let (mut parser, mut rx) = parser(FRAMED_SCRIPT);
+        parser.process_chunk(b"1,3,Heath KENNETT,58.20;\x01S\x0201T58.20\x03\x04");
+
+        let events = events(&mut rx);
+        assert_eq!(events.len(), 2, "{:?}", events);
+        assert!(matches!(&events[0], LynxEvent::ResultUpserted { result } if result.place == "1"));
+        assert!(matches!(&events[1], LynxEvent::SplitUpdated { index: 1, time: Some(time), .. } if time == "58.20"));
+    }
+
+    #[test]
+    fn framed_text_running_time() {
+        let (mut parser, mut rx) = parser(";;TimeRunning\n\\11\\01\\01R\\02%s\\03\\04\n");
+        parser.process_chunk(b"\x01R\x02       12.3\x03\x04");
+
+        let events = events(&mut rx);
+        assert_eq!(events.len(), 1, "{:?}", events);
+        assert!(matches!(&events[0], LynxEvent::ClockTick { time, ms: Some(12_300), precision: 1 } if time == "12.3"));
+    }
+
+    #[test]
+    fn framed_gun_time_is_not_the_running_time() {
+        let (mut parser, mut rx) = parser(";;TimeRunning\n\\11\\01%s\n;;TimeGun\n\\11\\01\\01G\\02%s\\03\\04\n");
+        parser.process_chunk(b"\x01G\x0210:00:00.0\x03\x04");
+        parser.process_chunk(b"        0.1");
+
+        let events = events(&mut rx);
+        assert_eq!(events.len(), 2, "{:?}", events);
+        assert!(matches!(&events[0], LynxEvent::GunStart { time, .. } if time == "10:00:00.0"));
+        assert!(matches!(&events[1], LynxEvent::ClockTick { time, .. } if time == "0.1"));
+    }
+
+    #[test]
+    fn partial_frame_waits_for_the_rest() {
+        let (mut parser, mut rx) = parser(FRAMED_SCRIPT);
+        parser.process_chunk(b"\x01");
+        parser.process_chunk(b"T\x0210.12\x03\x04");
+
+        let events = events(&mut rx);
+        assert_eq!(events.len(), 1, "{:?}", events);
+        assert!(matches!(&events[0], LynxEvent::TimeStopped { time, .. } if time == "10.12"));
+    }
+
+    // The bundled script frames both as \01T\02...\03\04, only the time of
+    // day's \B4\I4 padding tells them apart
+    #[test]
+    fn bundled_stopped_time() {
+        let (mut parser, mut rx) = with_decoder(LssDecoder::bundled());
+        parser.process_chunk(b"\x01T\x02          10.12\x03\x04Command=Trigger;Name=F1;Action=On;\n");
+
+        let events = events(&mut rx);
+        assert_eq!(events.len(), 2, "{:?}", events);
+        assert!(matches!(&events[0], LynxEvent::TimeStopped { time, ms: Some(10_120) } if time == "10.12"));
+        assert!(matches!(&events[1], LynxEvent::Command { command, .. } if command == "Trigger"));
+    }
+
+    #[test]
+    fn bundled_time_of_day() {
+        let (mut parser, mut rx) = with_decoder(LssDecoder::bundled());
+        parser.process_chunk(b"\x01T\x02     12:16:03    \x03\x04");
+
+        let events = events(&mut rx);
+        assert_eq!(events.len(), 1, "{:?}", events);
+        assert!(matches!(&events[0], LynxEvent::TimeOfDay { time } if time == "12:16:03"));
+    }
+}
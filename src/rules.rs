@@ -0,0 +1,300 @@
+use crate::config::BridgeConfig;
+use crate::events::{next_event, EventBus, LynxEvent, Origin};
+use crate::state::{ResultStatus, SharedState};
+use crate::titles::Source;
+use crate::vmix;
+use crate::vmix_tcp;
+use indexmap::IndexMap;
+use log::{debug, info, warn};
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+// Fires vMix functions when things happen on the track, e.g. bring the clock
+// in on the gun and swap to results once they're unofficial:
+//
+// "rules": [
+//   { "name": "Clock in", "on": "gun_start", "function": "OverlayInput1In", "input": "Clock" },
+//   { "name": "Results", "on": "header_received", "status": "UNOFFICIAL",
+//     "function": "Cut", "input": "Results", "debounce_ms": 60000 },
+//   { "name": "Event name", "on": "header_received", "function": "SetText",
+//     "input": "Results", "selected_name": "Event.Text", "source": "event_name" }
+// ]
+//
+// Command= lines from the script (timers, keys in FinishLynx) can drive
+// them too, and a rule can call a webhook or change the board instead:
+//
+//   { "name": "Photo", "on": "command", "match": { "Command": "Trigger", "Name": "F1" },
+//     "function": "OverlayInput2In", "input": "Photo" },
+//   { "name": "Companion", "on": "command", "match": { "Name": "F2" },
+//     "webhook": "http://127.0.0.1:8000/api/location/1/0/1/press" },
+//   { "name": "Clear", "on": "command", "match": { "Name": "F3" }, "state": "clear_messages" }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rule {
+    pub name: String,
+    pub enabled: bool,
+    pub on: Trigger,
+    pub status: Option<ResultStatus>, // Only for header_received, fire on this status only
+    #[serde(rename = "match")]
+    pub match_params: IndexMap<String, String>, // Only for command, all of these must match
+    pub function: String, // OverlayInput1In, Cut, SetText, TitleBeginAnimation...
+    pub input: String,
+    pub selected_name: String,
+    pub value: String,
+    pub source: Option<String>, // Title mapping expression, used as Value instead
+    pub webhook: String, // URL, gets the rule name and event POSTed as JSON
+    pub state: Option<StateAction>, // show_message uses the Value
+    pub debounce_ms: u64, // Ignore the trigger for this long after firing
+    pub delay_ms: u64,
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            enabled: true,
+            on: Trigger::GunStart,
+            status: None,
+            match_params: IndexMap::new(),
+            function: String::new(),
+            input: String::new(),
+            selected_name: String::new(),
+            value: String::new(),
+            source: None,
+            webhook: String::new(),
+            state: None,
+            debounce_ms: 0,
+            delay_ms: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    GunStart,
+    TimeStopped,
+    HeaderReceived,
+    LayoutDraw,
+    MessageSet,
+    Command,
+}
+
+// Changes to the board, made as if Lynx had sent them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateAction {
+    ClearResults,
+    ClearMessages,
+    ShowMessage,
+}
+
+impl Rule {
+    fn matches(&self, event: &LynxEvent) -> bool {
+        match (self.on, event) {
+            (Trigger::GunStart, LynxEvent::GunStart { .. }) => true,
+            (Trigger::TimeStopped, LynxEvent::TimeStopped { .. }) => true,
+            (Trigger::HeaderReceived, LynxEvent::HeaderReceived { info, .. }) => {
+                self.status.is_none() || info.status == self.status
+            }
+            (Trigger::LayoutDraw, LynxEvent::ResultsCleared) => true,
+            (Trigger::MessageSet, LynxEvent::MessageSet { .. }) => true,
+            (Trigger::Command, LynxEvent::Command { command, params }) => {
+                // Lynx isn't consistent about case, so neither are we
+                self.match_params.iter().all(|(key, expected)| {
+                    let actual = if key.eq_ignore_ascii_case("Command") {
+                        Some(command)
+                    } else {
+                        params.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v)
+                    };
+                    actual.is_some_and(|v| v.eq_ignore_ascii_case(expected))
+                })
+            }
+            _ => false,
+        }
+    }
+
+    fn value(&self, state: &SharedState, source: Option<&Source>) -> String {
+        match source {
+            Some(source) => {
+                let s = state.read();
+                let live: HashMap<String, String> = vmix::live_fields(&s).into_iter().collect();
+                source.resolve(&s, &live)
+            }
+            None => self.value.clone(),
+        }
+    }
+
+    fn command(&self, value: &str, has_source: bool) -> String {
+        // Cut and friends take no Value, but SetText with an empty one clears the field
+        let mut params = Vec::new();
+        if !self.input.is_empty() {
+            params.push(("Input", self.input.as_str()));
+        }
+        if !self.selected_name.is_empty() {
+            params.push(("SelectedName", self.selected_name.as_str()));
+        }
+        if !value.is_empty() || has_source {
+            params.push(("Value", value));
+        }
+        vmix_tcp::function(&self.function, &params)
+    }
+
+    fn state_event(&self, action: StateAction, value: String) -> LynxEvent {
+        match action {
+            StateAction::ClearResults => LynxEvent::ResultsCleared,
+            StateAction::ClearMessages => LynxEvent::MessageSet { messages: Vec::new() },
+            StateAction::ShowMessage => LynxEvent::MessageSet { messages: vec![value] },
+        }
+    }
+}
+
+// Rules go by the events the state has already applied (`applied`), so a
+// "source" reads the new race rather than the previous one. State actions
+// are published on `bus` like any other event.
+pub fn run_rules(
+    state: SharedState,
+    config: Arc<BridgeConfig>,
+    bus: &EventBus,
+    applied: &EventBus,
+    commands: mpsc::UnboundedSender<String>,
+) -> impl Future<Output = ()> + use<> {
+    let mut rx = applied.subscribe();
+    let bus = bus.clone();
+    async move {
+        let rules = &config.vmix.rules;
+        if rules.is_empty() {
+            return;
+        }
+        if !config.vmix.api.enabled && rules.iter().any(|rule| !rule.function.is_empty()) {
+            warn!("vMix rules are configured but the vMix TCP API is disabled, they won't fire");
+        }
+
+        // Bad expressions are reported once, and the rule stays off
+        let sources: Vec<Option<Source>> = rules.iter()
+            .map(|rule| rule.source.as_deref().and_then(|expr| match Source::parse(expr) {
+                Ok(source) => Some(source),
+                Err(e) => {
+                    warn!("Rule '{}': {}", rule.name, e);
+                    None
+                }
+            }))
+            .collect();
+        let mut last_fired: Vec<Option<Instant>> = vec![None; rules.len()];
+        let http = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(2))
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        while let Some((origin, event)) = next_event(&mut rx, "vMix rules").await {
+            // A clear_results rule on layout_draw would otherwise trigger itself forever
+            if origin == Origin::Rule {
+                continue;
+            }
+            for (idx, rule) in rules.iter().enumerate() {
+                if !rule.enabled || !rule.matches(&event) {
+                    continue;
+                }
+                if rule.source.is_some() && sources[idx].is_none() {
+                    continue;
+                }
+                if let Some(at) = last_fired[idx]
+                    && at.elapsed() < Duration::from_millis(rule.debounce_ms) {
+                    debug!("Rule '{}' debounced", rule.name);
+                    continue;
+                }
+                last_fired[idx] = Some(Instant::now());
+                info!("Rule '{}' fired", rule.name);
+
+                let rule = rule.clone();
+                let source = sources[idx].clone();
+                let state = state.clone();
+                let commands = commands.clone();
+                let bus = bus.clone();
+                let event = event.clone();
+                let http = http.clone();
+                tokio::spawn(async move {
+                    if rule.delay_ms > 0 {
+                        tokio::time::sleep(Duration::from_millis(rule.delay_ms)).await;
+                    }
+                    let value = rule.value(&state, source.as_ref());
+                    if !rule.function.is_empty() {
+                        let _ = commands.send(rule.command(&value, source.is_some()));
+                    }
+                    if let Some(action) = rule.state {
+                        bus.publish_from_rule(rule.state_event(action, value));
+                    }
+                    if !rule.webhook.is_empty() {
+                        let body = json!({ "rule": rule.name, "event": event });
+                        if let Err(e) = post_webhook(&http, &rule.webhook, &body).await {
+                            warn!("Rule '{}': webhook {} failed: {}", rule.name, rule.webhook, e);
+                        }
+                    }
+                });
+            }
+        }
+    }
+}
+
+// Companion, Node-RED and friends on the LAN, who shouldn't hold a rule up for long
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn post_webhook(http: &reqwest::Client, url: &str, body: &Value) -> Result<(), reqwest::Error> {
+    let response = http.post(url).json(body).send().await?.error_for_status()?;
+    debug!("Webhook {} -> {}", url, response.status());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{self, HeaderInfo};
+    use crate::vmix::VmixConfig;
+
+    fn header(event_name: &str) -> LynxEvent {
+        LynxEvent::HeaderReceived {
+            header: String::new(),
+            event_name: event_name.to_string(),
+            event_number: String::new(),
+            info: HeaderInfo::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn source_reads_the_new_header() {
+        let rule = Rule {
+            name: "Event name".to_string(),
+            on: Trigger::HeaderReceived,
+            function: "SetText".to_string(),
+            input: "Results".to_string(),
+            selected_name: "Event.Text".to_string(),
+            source: Some("event_name".to_string()),
+            ..Default::default()
+        };
+        let config = BridgeConfig {
+            vmix: VmixConfig { rules: vec![rule], ..Default::default() },
+            ..Default::default()
+        };
+        let state = SharedState::default();
+        state.write().apply(&header("Women 100m"));
+
+        let bus = EventBus::default();
+        let applied = EventBus::default();
+        let (commands_tx, mut commands) = mpsc::unbounded_channel();
+        tokio::spawn(run_rules(state.clone(), Arc::new(config), &bus, &applied, commands_tx));
+        let apply = state::apply_events(state, &bus, applied);
+
+        // Give the rules every chance to run before the state has caught up
+        bus.publish(header("Men 200m"));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        tokio::spawn(apply);
+        let command = tokio::time::timeout(Duration::from_secs(1), commands.recv()).await.unwrap().unwrap();
+        assert_eq!(command, "FUNCTION SetText Input=Results&SelectedName=Event.Text&Value=Men%20200m\r\n");
+    }
+}
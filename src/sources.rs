@@ -0,0 +1,386 @@
+use crate::state::now_millis;
+use indexmap::IndexMap;
+use log::{info, warn};
+use parking_lot::RwLock;
+use serde::{Serialize, Deserialize};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Where Lynx data comes from. With a main and a backup Lynx PC both
+// connected, only one of them (the active source) drives the scoreboard;
+// the others are parsed and counted but their events go nowhere.
+//
+// "sources": { "primary": ["192.168.1.10"], "backup": ["192.168.1.11"], "ignored": [] }
+//
+// Addresses not listed are primary if no primary is connected yet, backup
+// otherwise.
+//
+// When the primary goes quiet on time packets while a backup keeps sending
+// them, the backup takes over until the primary is back. Both have to hold
+// for the whole timeout: between races neither sends time, and whichever
+// speaks first at the next start shouldn't grab the board.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SourcesConfig {
+    pub primary: Vec<String>,
+    pub backup: Vec<String>,
+    pub ignored: Vec<String>,
+    pub failover_timeout_ms: u64, // 0 never fails over, only on disconnect
+}
+
+impl Default for SourcesConfig {
+    fn default() -> Self {
+        Self {
+            primary: Vec::new(),
+            backup: Vec::new(),
+            ignored: Vec::new(),
+            failover_timeout_ms: 2000,
+        }
+    }
+}
+
+// Kept for the status page
+const SWITCH_LOG: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceRole {
+    Primary,
+    Backup,
+    Ignored,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceInfo {
+    pub id: u64,
+    pub kind: &'static str, // "tcp", "tcp-out" (we dialed) or "udp"
+    pub peer: String,
+    pub role: SourceRole,
+    pub active: bool,
+    pub connected_at: u64, // Unix ms
+    pub last_activity: u64,
+    pub bytes: u64,
+    pub frames: u64, // Events the parser got out of it
+    pub last_time_packet: Option<u64>, // Running/stopped time, time of day or gun
+    pub sending_since: Option<u64>, // Start of the current run of time packets
+}
+
+impl SourceInfo {
+    fn label(&self) -> String {
+        format!("#{} {}", self.id, self.peer)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceSwitch {
+    pub at: u64, // Unix ms
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Default)]
+struct Registry {
+    next_id: u64,
+    failover_timeout_ms: u64,
+    sources: IndexMap<u64, SourceInfo>,
+    active: Option<u64>,
+    switches: VecDeque<SourceSwitch>,
+}
+
+impl Registry {
+    // Sent time recently enough to be trusted
+    fn is_live(&self, source: &SourceInfo, now: u64) -> bool {
+        self.failover_timeout_ms == 0
+            || source.last_time_packet.is_some_and(|t| now.saturating_sub(t) <= self.failover_timeout_ms)
+    }
+
+    // Has been sending time without a break for the whole timeout
+    fn is_steady(&self, source: &SourceInfo, now: u64) -> bool {
+        self.is_live(source, now)
+            && source.sending_since.is_some_and(|t| now.saturating_sub(t) >= self.failover_timeout_ms)
+    }
+
+    // Hasn't sent time for the whole timeout (or at all since connecting)
+    fn is_silent(&self, source: &SourceInfo, now: u64) -> bool {
+        self.failover_timeout_ms > 0
+            && now.saturating_sub(source.last_time_packet.unwrap_or(source.connected_at)) > self.failover_timeout_ms
+    }
+
+    // The first primary to connect wins, unless it has gone quiet while a
+    // backup kept going. Once on a backup we stay there until the primary is
+    // steady again. Without a reason, it's the watchdog checking on them.
+    fn elect(&mut self, reason: Option<&str>) {
+        let now = now_millis();
+        let primary = self.sources.values().find(|s| s.role == SourceRole::Primary);
+        let backups = || self.sources.values().filter(|s| s.role == SourceRole::Backup);
+        let on_backup = self.active
+            .and_then(|id| self.sources.get(&id))
+            .filter(|s| s.role == SourceRole::Backup);
+        let active = match (primary, on_backup) {
+            (Some(p), Some(backup)) => if self.is_steady(p, now) { Some(p) } else { Some(backup) },
+            (Some(p), None) => match backups().find(|s| self.is_steady(s, now)) {
+                Some(backup) if self.is_silent(p, now) => Some(backup),
+                _ => Some(p),
+            },
+            (None, _) => backups().find(|s| self.is_live(s, now)).or_else(|| backups().next()),
+        }.map(|s| s.id);
+        let primary = primary.map(|p| (p.id, p.label()));
+
+        for source in self.sources.values_mut() {
+            source.active = Some(source.id) == active;
+        }
+        if active == self.active {
+            return;
+        }
+
+        // The old one may have just disconnected
+        let label = |id: Option<u64>| id.map(|id| match self.sources.get(&id) {
+            Some(source) => source.label(),
+            None => format!("#{}", id),
+        });
+        let (from, to) = (label(self.active), label(active));
+        let failover = reason.is_none();
+        let reason = match reason {
+            Some(reason) => reason.to_string(),
+            None => match primary {
+                Some((id, label)) if Some(id) == active => format!("primary {} is sending time again", label),
+                Some((_, label)) => format!("primary {} sent no time for {} ms", label, self.failover_timeout_ms),
+                None => "primary gone".to_string(),
+            },
+        };
+        let describe = |label: &Option<String>| label.clone().unwrap_or_else(|| "none".to_string());
+        if failover {
+            warn!("Active source {} -> {}: {}", describe(&from), describe(&to), reason);
+        } else {
+            info!("Active source {} -> {}: {}", describe(&from), describe(&to), reason);
+        }
+
+        self.switches.push_back(SourceSwitch { at: now, from, to, reason });
+        if self.switches.len() > SWITCH_LOG {
+            self.switches.pop_front();
+        }
+        self.active = active;
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct SourceRegistry {
+    config: SourcesConfig,
+    inner: Arc<RwLock<Registry>>,
+}
+
+impl SourceRegistry {
+    pub fn new(config: SourcesConfig) -> Self {
+        let registry = Registry {
+            failover_timeout_ms: config.failover_timeout_ms,
+            ..Default::default()
+        };
+        Self {
+            config,
+            inner: Arc::new(RwLock::new(registry)),
+        }
+    }
+
+    pub fn register(&self, kind: &'static str, peer: SocketAddr) -> SourceHandle {
+        let ip = peer.ip().to_string();
+        let mut reg = self.inner.write();
+        let listed = |list: &[String]| list.contains(&ip);
+        let role = if listed(&self.config.ignored) {
+            SourceRole::Ignored
+        } else if listed(&self.config.primary) {
+            SourceRole::Primary
+        } else if listed(&self.config.backup) || reg.sources.values().any(|s| s.role == SourceRole::Primary) {
+            SourceRole::Backup
+        } else {
+            SourceRole::Primary
+        };
+
+        reg.next_id += 1;
+        let id = reg.next_id;
+        let now = now_millis();
+        info!("Source #{} {} connected as {:?}", id, peer, role);
+        reg.sources.insert(id, SourceInfo {
+            id,
+            kind,
+            peer: peer.to_string(),
+            role,
+            active: false,
+            connected_at: now,
+            last_activity: now,
+            bytes: 0,
+            frames: 0,
+            last_time_packet: None,
+            sending_since: None,
+        });
+        reg.elect(Some("connected"));
+        SourceHandle { id, registry: self.clone() }
+    }
+
+    pub fn list(&self) -> Vec<SourceInfo> {
+        self.inner.read().sources.values().cloned().collect()
+    }
+
+    // Newest first
+    pub fn switches(&self) -> Vec<SourceSwitch> {
+        self.inner.read().switches.iter().rev().cloned().collect()
+    }
+
+    // Fails over when the primary goes quiet, and back when it recovers
+    pub async fn watch(self) {
+        if self.config.failover_timeout_ms == 0 {
+            return;
+        }
+        let mut interval = tokio::time::interval(Duration::from_millis(250));
+        loop {
+            interval.tick().await;
+            self.inner.write().elect(None);
+        }
+    }
+
+    // From the status page, e.g. promote the backup by hand
+    pub fn set_role(&self, id: u64, role: SourceRole) -> bool {
+        let mut reg = self.inner.write();
+        let Some(source) = reg.sources.get_mut(&id) else { return false };
+        info!("Source #{} {} is now {:?}", id, source.peer, role);
+        source.role = role;
+        if role == SourceRole::Primary {
+            // There's only one primary, the old one steps down
+            for other in reg.sources.values_mut().filter(|s| s.id != id && s.role == SourceRole::Primary) {
+                other.role = SourceRole::Backup;
+            }
+        } else if let Some(source) = reg.sources.shift_remove(&id) {
+            // Back of the line, so a demoted source doesn't win the next election
+            reg.sources.insert(id, source);
+        }
+        reg.elect(Some("role changed by hand"));
+        true
+    }
+
+    fn remove(&self, id: u64) {
+        let mut reg = self.inner.write();
+        if let Some(source) = reg.sources.shift_remove(&id) {
+            info!("Source #{} {} disconnected", id, source.peer);
+        }
+        reg.elect(Some("disconnected"));
+    }
+}
+
+// One per connection, unregisters when the connection goes away
+pub struct SourceHandle {
+    id: u64,
+    registry: SourceRegistry,
+}
+
+impl SourceHandle {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.registry.inner.read().active == Some(self.id)
+    }
+
+    pub fn record_bytes(&self, n: usize) {
+        if let Some(source) = self.registry.inner.write().sources.get_mut(&self.id) {
+            source.bytes += n as u64;
+            source.last_activity = now_millis();
+        }
+    }
+
+    pub fn record_frame(&self, time_packet: bool) {
+        let mut reg = self.registry.inner.write();
+        let timeout = reg.failover_timeout_ms;
+        if let Some(source) = reg.sources.get_mut(&self.id) {
+            source.frames += 1;
+            if time_packet {
+                let now = now_millis();
+                // A gap longer than the timeout starts a new run
+                if source.last_time_packet.is_none_or(|t| now.saturating_sub(t) > timeout) {
+                    source.sending_since = Some(now);
+                }
+                source.last_time_packet = Some(now);
+            }
+        }
+    }
+}
+
+impl Drop for SourceHandle {
+    fn drop(&mut self) {
+        self.registry.remove(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> (SourceRegistry, SourceHandle, SourceHandle) {
+        let registry = SourceRegistry::new(SourcesConfig::default());
+        let primary = registry.register("tcp", "192.168.1.10:5000".parse().unwrap());
+        let backup = registry.register("tcp", "192.168.1.11:5000".parse().unwrap());
+        (registry, primary, backup)
+    }
+
+    // Sending time since `since` ms ago, last packet `last` ms ago
+    fn sending(registry: &SourceRegistry, handle: &SourceHandle, since: u64, last: u64) {
+        let now = now_millis();
+        let mut reg = registry.inner.write();
+        let source = reg.sources.get_mut(&handle.id()).unwrap();
+        source.connected_at = now - 60_000;
+        source.sending_since = Some(now - since);
+        source.last_time_packet = Some(now - last);
+    }
+
+    fn quiet(registry: &SourceRegistry, handle: &SourceHandle) {
+        let mut reg = registry.inner.write();
+        let source = reg.sources.get_mut(&handle.id()).unwrap();
+        source.connected_at = now_millis() - 60_000;
+        source.sending_since = None;
+        source.last_time_packet = None;
+    }
+
+    fn watchdog(registry: &SourceRegistry) {
+        registry.inner.write().elect(None);
+    }
+
+    #[test]
+    fn backup_speaking_first_does_not_take_over() {
+        let (registry, primary, backup) = registry();
+        quiet(&registry, &primary);
+        sending(&registry, &backup, 300, 0);
+        watchdog(&registry);
+        assert!(primary.is_active());
+
+        sending(&registry, &primary, 100, 0);
+        watchdog(&registry);
+        assert!(primary.is_active());
+        // Only the switch to the primary when it connected
+        assert_eq!(registry.switches().len(), 1);
+    }
+
+    #[test]
+    fn fails_over_and_back_without_flapping() {
+        let (registry, primary, backup) = registry();
+        sending(&registry, &primary, 30_000, 2_500);
+        sending(&registry, &backup, 30_000, 0);
+        watchdog(&registry);
+        assert!(backup.is_active());
+
+        // Between races neither sends, stay where we are
+        quiet(&registry, &primary);
+        quiet(&registry, &backup);
+        watchdog(&registry);
+        assert!(backup.is_active());
+
+        // The primary has to keep going for the whole timeout before it's trusted again
+        sending(&registry, &primary, 500, 0);
+        watchdog(&registry);
+        assert!(backup.is_active());
+        sending(&registry, &primary, 2_500, 0);
+        watchdog(&registry);
+        assert!(primary.is_active());
+    }
+}
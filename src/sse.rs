@@ -0,0 +1,254 @@
+use crate::state::{SharedState, Topic};
+use crate::web::topic_fields;
+use axum::response::sse::Event;
+use futures_util::stream::{self, Stream};
+use log::warn;
+use parking_lot::Mutex;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+// How many events a reconnecting client can catch up on
+const REPLAY_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub struct SseEvent {
+    pub id: Option<u64>, // Clock ticks have none, they aren't worth replaying
+    pub event: &'static str,
+    pub data: Value,
+}
+
+impl SseEvent {
+    fn to_event(&self) -> Event {
+        let event = Event::default()
+            .event(self.event)
+            .data(self.data.to_string());
+        match self.id {
+            Some(id) => event.id(id.to_string()),
+            None => event,
+        }
+    }
+}
+
+#[derive(Default)]
+struct SseLog {
+    next_id: u64,
+    events: VecDeque<SseEvent>,
+}
+
+impl SseLog {
+    fn last_id(&self) -> u64 {
+        self.next_id.saturating_sub(1)
+    }
+
+    // Everything after `last_id`, None if some of it has already been dropped
+    fn since(&self, last_id: u64) -> Option<Vec<SseEvent>> {
+        if last_id > self.last_id() {
+            return None; // Bridge restarted, the ids mean nothing now
+        }
+        let oldest = self.events.front().and_then(|e| e.id).unwrap_or(self.next_id);
+        if last_id + 1 < oldest {
+            return None;
+        }
+        Some(self.events.iter()
+            .filter(|e| e.id.is_some_and(|id| id > last_id))
+            .cloned()
+            .collect())
+    }
+}
+
+// Turns the parser's change notifications into typed SSE events and keeps
+// the recent ones around for Last-Event-ID.
+#[derive(Clone)]
+pub struct SseHub {
+    log: Arc<Mutex<SseLog>>,
+    tx: broadcast::Sender<SseEvent>,
+}
+
+impl SseHub {
+    pub fn start(state: SharedState) -> Self {
+        let hub = Self {
+            log: Arc::new(Mutex::new(SseLog { next_id: 1, ..Default::default() })),
+            tx: broadcast::channel(256).0,
+        };
+        let task_hub = hub.clone();
+        tokio::spawn(async move { task_hub.run(state).await });
+        hub
+    }
+
+    async fn run(&self, state: SharedState) {
+        let mut changes = state.read().changes.subscribe();
+        let mut builder = EventBuilder::default();
+        builder.prime(&state);
+
+        loop {
+            let topic = match changes.recv().await {
+                Ok(topic) => topic,
+                Err(RecvError::Lagged(n)) => {
+                    warn!("SSE hub missed {} state changes", n);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            for event in builder.build(&state, topic) {
+                self.publish(event);
+            }
+        }
+    }
+
+    fn publish(&self, mut event: SseEvent) {
+        if event.event != "clock" {
+            let mut log = self.log.lock();
+            event.id = Some(log.next_id);
+            log.next_id += 1;
+            log.events.push_back(event.clone());
+            if log.events.len() > REPLAY_LIMIT {
+                log.events.pop_front();
+            }
+        }
+        let _ = self.tx.send(event);
+    }
+
+    pub fn stream(&self, state: SharedState, last_event_id: Option<u64>) -> impl Stream<Item = Result<Event, Infallible>> + use<> {
+        // Subscribe before looking at the log so nothing slips through in between
+        let rx = self.tx.subscribe();
+        let initial = {
+            let log = self.log.lock();
+            match last_event_id.and_then(|id| log.since(id)) {
+                Some(missed) => missed,
+                None => snapshot_events(&state, log.last_id()),
+            }
+        };
+        let sent_up_to = initial.iter().filter_map(|e| e.id).max().unwrap_or(0);
+
+        stream::unfold(
+            (VecDeque::from(initial), rx, sent_up_to, state),
+            |(mut pending, mut rx, mut sent_up_to, state)| async move {
+                loop {
+                    if let Some(event) = pending.pop_front() {
+                        return Some((Ok(event.to_event()), (pending, rx, sent_up_to, state)));
+                    }
+                    match rx.recv().await {
+                        Ok(event) => {
+                            if let Some(id) = event.id {
+                                if id <= sent_up_to { continue; }
+                                sent_up_to = id;
+                            }
+                            pending.push_back(event);
+                        }
+                        // Fell behind, start them over from the current state
+                        Err(RecvError::Lagged(_)) => pending.extend(snapshot_events(&state, sent_up_to)),
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            },
+        )
+    }
+}
+
+// Current state as events, for clients that are new or too far behind to replay
+fn snapshot_events(state: &SharedState, id: u64) -> Vec<SseEvent> {
+    let s = state.read();
+    let id = Some(id);
+    let mut events = vec![
+        SseEvent { id, event: "header", data: topic_fields(&s, Topic::Header) },
+        SseEvent { id, event: "phase", data: topic_fields(&s, Topic::Phase) },
+        SseEvent { id, event: "wind", data: topic_fields(&s, Topic::Wind) },
+        SseEvent { id, event: "splits", data: topic_fields(&s, Topic::Splits) },
+        SseEvent { id, event: "message", data: topic_fields(&s, Topic::Messages) },
+        SseEvent { id, event: "page", data: topic_fields(&s, Topic::Page) },
+        SseEvent { id: None, event: "clock", data: topic_fields(&s, Topic::Clock) },
+    ];
+    for r in &s.results {
+        events.push(SseEvent { id, event: "result", data: serde_json::json!(r) });
+    }
+    events
+}
+
+// Lynx resends whole result lists, so remember what went out last time and
+// only send the athletes that actually changed.
+#[derive(Default)]
+struct EventBuilder {
+    results: HashMap<String, Value>,
+    race_key: String,
+    page: Value,
+}
+
+impl EventBuilder {
+    fn prime(&mut self, state: &SharedState) {
+        let s = state.read();
+        self.race_key = s.race_key();
+        self.results = s.results.iter()
+            .map(|r| (result_key(r), serde_json::json!(r)))
+            .collect();
+    }
+
+    fn build(&mut self, state: &SharedState, topic: Topic) -> Vec<SseEvent> {
+        let s = state.read();
+        let event = |event, data| SseEvent { id: None, event, data };
+
+        match topic {
+            Topic::Clock => vec![event("clock", topic_fields(&s, topic))],
+            Topic::Phase => vec![event("phase", topic_fields(&s, topic))],
+            Topic::Wind => vec![event("wind", topic_fields(&s, topic))],
+            Topic::Splits => vec![event("splits", topic_fields(&s, topic))],
+            Topic::Messages => vec![event("message", topic_fields(&s, topic))],
+            // Comes with every result line, only send it when the page looks different
+            Topic::Page => {
+                let data = topic_fields(&s, topic);
+                if data == self.page {
+                    return Vec::new();
+                }
+                self.page = data.clone();
+                vec![event("page", data)]
+            }
+            Topic::Header => {
+                let mut events = Vec::new();
+                // A different race on the board, the last one is now history
+                let key = s.race_key();
+                if !self.race_key.is_empty() && key != self.race_key
+                    && let Some(race) = s.races.get(&self.race_key) {
+                    events.push(event("race_archived", serde_json::json!({
+                        "key": self.race_key,
+                        "race": race,
+                    })));
+                }
+                self.race_key = key;
+                events.push(event("header", topic_fields(&s, topic)));
+                events
+            }
+            Topic::Results => {
+                if s.results.is_empty() {
+                    if self.results.is_empty() {
+                        return Vec::new();
+                    }
+                    self.results.clear();
+                    return vec![event("results_cleared", serde_json::json!({}))];
+                }
+                let mut events = Vec::new();
+                for r in &s.results {
+                    let data = serde_json::json!(r);
+                    let key = result_key(r);
+                    if self.results.get(&key) != Some(&data) {
+                        self.results.insert(key, data.clone());
+                        events.push(event("result", data));
+                    }
+                }
+                events
+            }
+        }
+    }
+}
+
+// Same matching the parser uses for upserts
+fn result_key(r: &crate::state::AthleteResult) -> String {
+    if !r.lane.is_empty() {
+        format!("lane:{}", r.lane)
+    } else if !r.place.is_empty() {
+        format!("place:{}", r.place)
+    } else {
+        format!("id:{}", r.id)
+    }
+}
@@ -1,34 +1,567 @@
 use serde::{Serialize, Deserialize};
+use log::{debug, info, warn};
 use indexmap::IndexMap;
+use crate::clock::ClockModel;
+use crate::events::{next_event, EventBus, LynxEvent};
+use tokio::sync::broadcast;
+use crate::time::{format_gap, RaceTime};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ScoreboardState {
     pub time: String,
-    pub running: bool,
+    pub time_ms: Option<u64>, // Running time in ms, when it parses
+    pub clock_time: String, // Running time ticking smoothly between Lynx packets
+    pub clock_ms: Option<u64>,
+    #[serde(skip)]
+    pub clock: ClockModel,
+    pub running: bool, // phase == Running
+    pub phase: RacePhase,
+    pub phase_since: u64, // Unix ms
+    pub phase_times: PhaseTimes,
     pub results: Vec<AthleteResult>,
     pub messages: Vec<String>,
     pub event_name: String,
     pub event_number: String,
     pub gun_time: String,
+    pub gun_time_ms: Option<u64>, // Time of day, ms since midnight
+    pub finish_time: String, // From TimeStopped
+    pub finish_time_ms: Option<u64>,
+    pub clock_frozen: bool, // Hold the finish time on the clock until the next race
+    pub time_of_day: String, // Wall clock from TimeOfDay
+    pub wind_reading: Option<WindReading>,
+    pub splits: Vec<Split>, // Intermediate break times, ordered by break number
+    pub page: usize, // Results page on show when rotating, 0-based (see pages.rs)
+    pub page_size: usize,
     pub header: String, // Stored raw header
+    #[serde(flatten)]
+    pub info: HeaderInfo, // Typed header fields
     
-    // History of races, keyed by event_number
+    // History of races, keyed by event-round-heat (see race_key)
     pub races: IndexMap<String, RaceData>,
 
     // For intermediate parsing verification or debugging
     pub last_packet: String, 
+
+    #[serde(skip)]
+    pub changes: ChangeFeed,
+    // A race has started but its header hasn't come yet, so race_key()
+    // still names the previous one
+    #[serde(skip)]
+    awaiting_header: bool,
+    #[serde(skip)]
+    last_tick_ms: Option<u64>, // Even while the finish time is frozen on the board
+}
+
+// Which part of the state changed, so pushed clients only get what they asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Topic {
+    Clock,
+    Phase,
+    Header,
+    Results,
+    Messages,
+    Wind,
+    Splits,
+    Page,
+}
+
+impl Topic {
+    pub const ALL: [Topic; 8] = [
+        Topic::Clock, Topic::Phase, Topic::Header, Topic::Results,
+        Topic::Messages, Topic::Wind, Topic::Splits, Topic::Page,
+    ];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.name() == s.trim().to_lowercase())
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Topic::Clock => "clock",
+            Topic::Phase => "phase",
+            Topic::Header => "header",
+            Topic::Results => "results",
+            Topic::Messages => "messages",
+            Topic::Wind => "wind",
+            Topic::Splits => "splits",
+            Topic::Page => "page",
+        }
+    }
+}
+
+// Which part of the state changed, after the change is applied. The outputs
+// (web push, SSE, vMix) render the state rather than the wire, and not every
+// change comes off the bus: the clock ticker and the page rotation move the
+// state on their own. Following the bus they would render before the event
+// is applied and miss those changes.
+#[derive(Debug, Clone)]
+pub struct ChangeFeed(broadcast::Sender<Topic>);
+
+impl Default for ChangeFeed {
+    fn default() -> Self {
+        Self(broadcast::channel(256).0)
+    }
+}
+
+impl ChangeFeed {
+    pub fn subscribe(&self) -> broadcast::Receiver<Topic> {
+        self.0.subscribe()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct RaceData {
     pub event_name: String,
     pub event_number: String,
     pub gun_time: String,
+    pub finish_time: String,
+    pub wind_reading: Option<WindReading>,
+    pub splits: Vec<Split>,
     pub header: String,
+    #[serde(flatten)]
+    pub info: HeaderInfo,
     pub results: Vec<AthleteResult>,
 }
 
+impl ScoreboardState {
+    // Nobody listening is fine
+    pub fn notify(&self, topic: Topic) {
+        let _ = self.changes.0.send(topic);
+        // The current page is cut from the results
+        if topic == Topic::Results {
+            let _ = self.changes.0.send(Topic::Page);
+        }
+    }
+
+    fn set_messages(&mut self, messages: Vec<String>) {
+        self.messages = messages;
+        self.notify(Topic::Messages);
+    }
+
+    // History key for the race currently on the scoreboard
+    pub fn race_key(&self) -> String {
+        race_key(&self.event_number, &self.info)
+    }
+
+    // Wind for the current race, from the ;;Wind section or the header column
+    fn set_wind(&mut self, mut reading: WindReading) {
+        reading.legal = wind_applies(&self.event_name).then_some(reading.value <= WIND_LIMIT);
+        debug!("Wind: {} (legal: {:?})", reading.display, reading.legal);
+
+        self.update_race(|race| race.wind_reading = Some(reading.clone()));
+        self.wind_reading = Some(reading);
+        self.notify(Topic::Wind);
+    }
+
+    // Break time/name nn from the ;;TimeBreak lines, either may come first
+    fn set_split(&mut self, index: u8, name: Option<String>, time: Option<String>) {
+        upsert_split(&mut self.splits, index, name.clone(), time.clone());
+        self.update_race(|race| upsert_split(&mut race.splits, index, name, time));
+        self.notify(Topic::Splits);
+    }
+
+    // A running time from Lynx, the smoothed clock snaps back to it
+    fn set_running_time(&mut self, time: String, ms: Option<u64>, precision: u8) {
+        if let Some(ms) = ms {
+            self.clock.snap(ms, precision);
+        }
+        self.clock_time = time.clone();
+        self.clock_ms = ms;
+        self.time = time;
+        self.time_ms = ms;
+        self.notify(Topic::Clock);
+    }
+
+    // History of the race on the board. Until the new race's header is in,
+    // what it sets only lives on the board, and is copied over with the header.
+    fn update_race(&mut self, update: impl FnOnce(&mut RaceData)) {
+        if self.awaiting_header {
+            return;
+        }
+        let key = self.race_key();
+        if !key.is_empty() {
+            self.races.entry(key).and_modify(update);
+        }
+    }
+
+    // The previous race's times, wind and splits come off the board
+    fn start_race(&mut self) {
+        self.awaiting_header = true;
+        self.last_tick_ms = None;
+        self.gun_time.clear();
+        self.gun_time_ms = None;
+        self.finish_time.clear();
+        self.finish_time_ms = None;
+        self.clock_frozen = false;
+        self.wind_reading = None;
+        self.splits.clear();
+        for topic in [Topic::Clock, Topic::Wind, Topic::Splits] {
+            self.notify(topic);
+        }
+    }
+
+    fn advance_phase(&mut self, trigger: PhaseTrigger) {
+        let Some(next) = self.phase.next(trigger) else { return };
+        if next == self.phase { return; }
+
+        let now = now_millis();
+        log::info!("Race phase {:?} -> {:?} ({:?})", self.phase, next, trigger);
+
+        // A new race starts over, forget the previous race's later phases
+        if matches!(next, RacePhase::Armed | RacePhase::Running) {
+            self.phase_times.clear_after(next);
+        }
+        self.phase_times.set(next, now);
+        self.phase = next;
+        self.phase_since = now;
+        self.running = next == RacePhase::Running;
+        self.notify(Topic::Phase);
+    }
+
+    // Everything the parser reports ends up on the scoreboard through here
+    pub fn apply(&mut self, event: &LynxEvent) {
+        match event {
+            LynxEvent::ClockTick { time, ms, precision } => {
+                // TimeGun ignores manual starts, so the next race may only
+                // show up as the running time going back
+                if let (Some(ms), Some(last)) = (ms, self.last_tick_ms)
+                    && ms + RESTART_SLACK_MS < last {
+                    log::info!("Running time went back to {}, new race", time);
+                    self.start_race();
+                    self.advance_phase(PhaseTrigger::ClockRestart);
+                }
+                if ms.is_some() {
+                    self.last_tick_ms = *ms;
+                }
+                // Hold the finish time until the next race
+                if !self.clock_frozen {
+                    self.set_running_time(time.clone(), *ms, *precision);
+                }
+                self.advance_phase(PhaseTrigger::RunningTime);
+            }
+            LynxEvent::GunStart { time, ms } => {
+                self.start_race();
+                self.gun_time = time.clone();
+                self.gun_time_ms = *ms;
+                self.clock.restart();
+                self.advance_phase(PhaseTrigger::GunStart);
+            }
+            LynxEvent::TimeStopped { time, ms } => {
+                // Freeze the display clock on the finish time until the next race
+                self.time = time.clone();
+                self.time_ms = *ms;
+                self.clock_time = time.clone();
+                self.clock_ms = *ms;
+                self.finish_time = time.clone();
+                self.finish_time_ms = *ms;
+                self.clock_frozen = true;
+                self.notify(Topic::Clock);
+                self.advance_phase(PhaseTrigger::TimeStopped);
+                self.update_race(|race| race.finish_time = time.clone());
+            }
+            LynxEvent::TimeOfDay { time } => {
+                self.time_of_day = time.clone();
+                self.notify(Topic::Clock);
+            }
+            LynxEvent::HeaderReceived { header, event_name, event_number, info } => {
+                self.results.clear(); // Start of new list
+                self.header = header.clone();
+                self.event_name = event_name.clone();
+                self.event_number = event_number.clone();
+                self.info = info.clone();
+                if std::mem::take(&mut self.awaiting_header) {
+                    let (gun_time, finish_time) = (self.gun_time.clone(), self.finish_time.clone());
+                    let (wind_reading, splits) = (self.wind_reading.clone(), self.splits.clone());
+                    self.update_race(|race| {
+                        race.gun_time = gun_time;
+                        race.finish_time = finish_time;
+                        race.wind_reading = wind_reading;
+                        race.splits = splits;
+                    });
+                }
+                self.notify(Topic::Header);
+                self.notify(Topic::Results);
+                // "nwi" (no wind info) keeps whatever ;;Wind reported
+                if let Some(reading) = WindReading::parse(&info.wind) {
+                    self.set_wind(reading);
+                }
+                if let Some(status) = info.status {
+                    self.advance_phase(PhaseTrigger::Header(status));
+                }
+            }
+            LynxEvent::ResultUpserted { result } => self.upsert_result(result),
+            LynxEvent::ResultsCleared => {
+                self.results.clear();
+                self.start_race();
+                self.clock.reset();
+                self.notify(Topic::Results);
+                self.advance_phase(PhaseTrigger::LayoutDraw);
+            }
+            LynxEvent::MessageSet { messages } => self.set_messages(messages.clone()),
+            LynxEvent::WindMeasured { reading } => self.set_wind(reading.clone()),
+            LynxEvent::SplitUpdated { index, name, time } => self.set_split(*index, name.clone(), time.clone()),
+            // Nothing on the board, these are for the rules
+            LynxEvent::Command { .. } => {}
+        }
+    }
+
+    fn upsert_result(&mut self, res: &AthleteResult) {
+        // Try to match by Lane first, then Place, then ID
+        let matches = |r: &AthleteResult| {
+            (!res.lane.is_empty() && r.lane == res.lane) ||
+            (res.lane.is_empty() && !res.place.is_empty() && r.place == res.place) ||
+            (res.lane.is_empty() && res.place.is_empty() && !res.id.is_empty() && r.id == res.id)
+        };
+
+        match self.results.iter().position(matches) {
+            Some(idx) => self.results[idx] = res.clone(),
+            None => self.results.push(res.clone()),
+        }
+        sort_results(&mut self.results);
+        self.notify(Topic::Results);
+
+        // Update History
+        let key = self.race_key();
+        if key.is_empty() { return; }
+
+        let race = self.races.entry(key).or_insert_with(|| RaceData {
+            event_name: self.event_name.clone(),
+            event_number: self.event_number.clone(),
+            gun_time: self.gun_time.clone(),
+            finish_time: self.finish_time.clone(),
+            wind_reading: self.wind_reading.clone(),
+            splits: self.splits.clone(),
+            ..Default::default()
+        });
+        race.header = self.header.clone();
+        race.info = self.info.clone();
+        match race.results.iter().position(matches) {
+            Some(idx) => race.results[idx] = res.clone(),
+            None => race.results.push(res.clone()),
+        }
+        sort_results(&mut race.results);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct Split {
+    pub index: u8,
+    pub name: String, // "400m"
+    pub time: String,
+}
+
+fn upsert_split(splits: &mut Vec<Split>, index: u8, name: Option<String>, time: Option<String>) {
+    let pos = match splits.iter().position(|s| s.index == index) {
+        Some(pos) => pos,
+        None => {
+            splits.push(Split { index, ..Default::default() });
+            splits.sort_by_key(|s| s.index);
+            splits.iter().position(|s| s.index == index).unwrap()
+        }
+    };
+    if let Some(name) = name { splits[pos].name = name; }
+    if let Some(time) = time { splits[pos].time = time; }
+}
+
+// Records with more than +2.0 m/s of tailwind don't count
+pub const WIND_LIMIT: f64 = 2.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WindReading {
+    pub value: f64, // m/s, negative is headwind
+    pub display: String, // "+1.2"
+    pub legal: Option<bool>, // Only set for events where wind matters
+}
+
+impl WindReading {
+    pub fn from_value(value: f64) -> Self {
+        Self { value, display: format!("{:+.1}", value), legal: None }
+    }
+
+    // Formatted wind ("+1.2", "-0.4 m/s"). "nwi" and blanks mean no wind info.
+    pub fn parse(value: &str) -> Option<Self> {
+        let cleaned = value.trim().trim_end_matches("m/s").trim();
+        cleaned.parse::<f64>().ok().map(Self::from_value)
+    }
+
+    // Binary wind is in hundredths of units
+    pub fn from_hundredths(hundredths: i32) -> Self {
+        Self::from_value(hundredths as f64 / 100.0)
+    }
+}
+
+// Wind only matters for sprints up to 200m, the short hurdles and the horizontal jumps
+pub fn wind_applies(event_name: &str) -> bool {
+    static WIND_EVENTS: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();
+    let re = WIND_EVENTS.get_or_init(|| {
+        regex::Regex::new(r"(?i)(^|[^\w])(100|110|200)\s?m?h?([^\w]|$)|long\s?jump|triple\s?jump|\bLJ\b|\bTJ\b").unwrap()
+    });
+    re.is_match(event_name)
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum RacePhase {
+    #[default]
+    Idle,
+    Armed,
+    Running,
+    Stopped,
+    Unofficial,
+    Official,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhaseTrigger {
+    LayoutDraw,
+    GunStart,
+    ClockRestart, // Running time went backwards, a race started without the gun
+    RunningTime,
+    TimeStopped,
+    Header(ResultStatus),
+}
+
+impl RacePhase {
+    // Idle -> Armed (LayoutDraw) -> Running (gun / first time packet / clock starting over)
+    //      -> Stopped (beam break) -> Unofficial -> Official (results header status)
+    pub fn next(self, trigger: PhaseTrigger) -> Option<RacePhase> {
+        use RacePhase::*;
+        match (self, trigger) {
+            (_, PhaseTrigger::LayoutDraw) => Some(Armed),
+            (_, PhaseTrigger::GunStart | PhaseTrigger::ClockRestart) => Some(Running),
+            // The clock keeps ticking after the first finisher, that doesn't restart the race
+            (Idle | Armed, PhaseTrigger::RunningTime) => Some(Running),
+            (Idle | Armed | Running, PhaseTrigger::TimeStopped) => Some(Stopped),
+            // Start lists carry a header too, only results after the race count
+            (Idle | Running | Stopped, PhaseTrigger::Header(ResultStatus::Unofficial)) => Some(Unofficial),
+            (_, PhaseTrigger::Header(ResultStatus::Official)) => Some(Official),
+            _ => None,
+        }
+    }
+}
+
+// When each phase was last entered (Unix ms)
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PhaseTimes {
+    pub idle: Option<u64>,
+    pub armed: Option<u64>,
+    pub running: Option<u64>,
+    pub stopped: Option<u64>,
+    pub unofficial: Option<u64>,
+    pub official: Option<u64>,
+}
+
+impl PhaseTimes {
+    fn slot(&mut self, phase: RacePhase) -> &mut Option<u64> {
+        match phase {
+            RacePhase::Idle => &mut self.idle,
+            RacePhase::Armed => &mut self.armed,
+            RacePhase::Running => &mut self.running,
+            RacePhase::Stopped => &mut self.stopped,
+            RacePhase::Unofficial => &mut self.unofficial,
+            RacePhase::Official => &mut self.official,
+        }
+    }
+
+    fn set(&mut self, phase: RacePhase, at: u64) {
+        *self.slot(phase) = Some(at);
+    }
+
+    fn clear_after(&mut self, phase: RacePhase) {
+        for later in [RacePhase::Running, RacePhase::Stopped, RacePhase::Unofficial, RacePhase::Official] {
+            if later > phase {
+                *self.slot(later) = None;
+            }
+        }
+    }
+}
+
+pub fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+// Lynx can send a running time a hair behind the last one, a new race starts
+// from zero and is well behind
+const RESTART_SLACK_MS: u64 = 1000;
+
+// Heats of the same event share an event number, so key on event-round-heat.
+// Prefer the combined id Lynx already sends ("15B-1-01").
+pub fn race_key(event_number: &str, info: &HeaderInfo) -> String {
+    if !info.race_id.is_empty() {
+        return info.race_id.clone();
+    }
+    if event_number.is_empty() {
+        return String::new();
+    }
+    match (info.round, info.heat) {
+        (Some(round), Some(heat)) => format!("{}-{}-{:02}", event_number, round, heat),
+        _ => event_number.to_string(),
+    }
+}
+
+// Everything in the results header besides event name/number
+// "UNOFFICIAL,15B Group 1 1500m 111m,nwi,15B,1,01,15B-1-01,AUTO,7"
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct HeaderInfo {
+    pub status: Option<ResultStatus>,
+    pub wind: String,
+    pub round: Option<u32>,
+    pub heat: Option<u32>,
+    pub start_type: Option<StartType>,
+    pub participants: Option<u32>,
+    pub race_id: String, // Combined "15B-1-01"
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ResultStatus {
+    Official,
+    Unofficial,
+}
+
+impl ResultStatus {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "OFFICIAL" => Some(ResultStatus::Official),
+            "UNOFFICIAL" => Some(ResultStatus::Unofficial),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum StartType {
+    Auto,
+    Manual,
+}
+
+impl StartType {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "AUTO" => Some(StartType::Auto),
+            "MANUAL" => Some(StartType::Manual),
+            _ => None,
+        }
+    }
+}
+
+// Lynx pads round/heat with zeros ("01")
+pub fn parse_number(value: &str) -> Option<u32> {
+    value.trim().parse().ok()
+}
+
+// One line per LSS result variable (\14\01 - \14\0f).
+// Older history files only have the first seven, hence the serde default.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct AthleteResult {
     pub place: String,
     pub lane: String,
@@ -37,7 +570,18 @@ pub struct AthleteResult {
     pub affiliation: String,
     pub time: String,
     pub delta_time: String,
-    // Add more fields as needed from LSS
+    pub cumulative_split: String,
+    pub last_split: String,
+    pub laps_to_go: String,
+    pub license: String,
+    pub reaction_time: String,
+    pub speed: String,
+    pub pace: String,
+    pub best_split: String,
+    // Parsed from the strings above (time_value.raw is what Lynx sent)
+    pub time_value: Option<RaceTime>,
+    pub delta_value: Option<RaceTime>,
+    pub gap: String, // Behind the fastest time, e.g. "+1.23"
 }
 
 // We will use Parking Lot RwLock for the global state
@@ -49,35 +593,124 @@ use std::path::Path;
 
 pub type SharedState = Arc<RwLock<ScoreboardState>>;
 
-const HISTORY_FILE: &str = "events-history.json";
+pub const HISTORY_FILE: &str = "events-history.json";
 
-pub fn initialize_state() -> SharedState {
+pub fn initialize_state(history_file: &str) -> SharedState {
     let mut state = ScoreboardState::default();
-    state.races = load_history();
+    state.races = load_history(history_file);
+    state.phase_since = now_millis();
+    state.phase_times.idle = Some(state.phase_since);
     Arc::new(RwLock::new(state))
 }
 
-pub fn load_history() -> IndexMap<String, RaceData> {
-    if Path::new(HISTORY_FILE).exists() {
-        if let Ok(content) = fs::read_to_string(HISTORY_FILE) {
+pub fn load_history(path: &str) -> IndexMap<String, RaceData> {
+    if Path::new(path).exists() {
+        if let Ok(content) = fs::read_to_string(path) {
             if let Ok(data) = serde_json::from_str(&content) {
-                println!("Loaded history from {}", HISTORY_FILE);
-                return data;
+                info!("Loaded history from {}", path);
+                let (races, migrated) = migrate_history(data);
+                if migrated {
+                    // The operator's only copy, keep the old one in case the migration is wrong
+                    let backup = format!("{}.bak", path);
+                    match fs::copy(path, &backup) {
+                        Ok(_) => {
+                            info!("Migrated {} to event-round-heat keys, the old file is in {}", path, backup);
+                            save_history(path, &races);
+                        }
+                        Err(e) => warn!("Not migrating {} on disk, could not back it up to {}: {}", path, backup, e),
+                    }
+                }
+                return races;
             }
         }
     }
     IndexMap::new()
 }
 
-pub fn save_history(races: &IndexMap<String, RaceData>) {
+// Older files were keyed by event number alone and only kept the raw header.
+// Those headers were written with the vmix.lss column order:
+// Status, Event Name, Wind, Event Number, Round, Heat, Race Id, Start Type, Participants
+fn migrate_history(races: IndexMap<String, RaceData>) -> (IndexMap<String, RaceData>, bool) {
+    let mut migrated = false;
+    let mut out = IndexMap::new();
+
+    for (old_key, mut race) in races {
+        if race.info == HeaderInfo::default() && !race.header.is_empty() {
+            let params: Vec<&str> = race.header.split(',').map(|p| p.trim()).collect();
+            let param = |idx: usize| params.get(idx).copied().unwrap_or_default();
+            race.info = HeaderInfo {
+                status: ResultStatus::parse(param(0)),
+                wind: param(2).to_string(),
+                round: parse_number(param(4)),
+                heat: parse_number(param(5)),
+                start_type: StartType::parse(param(7)),
+                participants: parse_number(param(8)),
+                race_id: param(6).to_string(),
+            };
+            migrated = true;
+        }
+
+        let key = race_key(&race.event_number, &race.info);
+        let key = if key.is_empty() { old_key.clone() } else { key };
+        if key != old_key {
+            migrated = true;
+        }
+        out.insert(key, race);
+    }
+
+    (out, migrated)
+}
+
+// Applies parser events to the shared scoreboard, then passes each one on to
+// `applied`. Subscribes straight away so nothing published before the task
+// first runs is lost.
+pub fn apply_events(state: SharedState, bus: &EventBus, applied: EventBus) -> impl Future<Output = ()> + use<> {
+    let mut rx = bus.subscribe();
+    async move {
+        while let Some((origin, event)) = next_event(&mut rx, "Scoreboard state").await {
+            state.write().apply(&event);
+            applied.send(origin, event);
+        }
+    }
+}
+
+// Writes the history file shortly after a change, so a burst of result
+// lines is one write rather than one per line
+pub fn persist_history(state: SharedState, bus: &EventBus, path: String) -> impl Future<Output = ()> + use<> {
+    let mut rx = bus.subscribe();
+    async move {
+        while let Some((_, event)) = next_event(&mut rx, "History").await {
+            if matches!(event, LynxEvent::ClockTick { .. } | LynxEvent::TimeOfDay { .. } | LynxEvent::MessageSet { .. } | LynxEvent::Command { .. }) {
+                continue;
+            }
+            // Soak up the rest of the burst (the clock keeps ticking meanwhile)
+            let deadline = tokio::time::Instant::now() + std::time::Duration::from_millis(250);
+            while let Ok(Some(_)) = tokio::time::timeout_at(deadline, next_event(&mut rx, "History")).await {}
+            let races = state.read().races.clone();
+            save_history(&path, &races);
+        }
+    }
+}
+
+pub fn save_history(path: &str, races: &IndexMap<String, RaceData>) {
     if let Ok(json) = serde_json::to_string_pretty(races) {
-        if let Err(e) = fs::write(HISTORY_FILE, json) {
-            eprintln!("Failed to save history: {}", e);
+        if let Err(e) = fs::write(path, json) {
+            eprintln!("Failed to save history to {}: {}", path, e);
         }
     }
 }
 
 pub fn sort_results(results: &mut Vec<AthleteResult>) {
+    // Fill in anything that came from an older history file
+    for r in results.iter_mut() {
+        if r.time_value.is_none() {
+            r.time_value = RaceTime::parse(&r.time);
+        }
+        if r.delta_value.is_none() {
+            r.delta_value = RaceTime::parse(&r.delta_time);
+        }
+    }
+
     results.sort_by(|a, b| {
         let place_a = a.place.parse::<u32>().ok();
         let place_b = b.place.parse::<u32>().ok();
@@ -88,7 +721,7 @@ pub fn sort_results(results: &mut Vec<AthleteResult>) {
         // Logic:
         // 1. If both have place, compare place
         // 2. If only one has place, that one comes first
-        // 3. If neither has place, compare lane
+        // 3. If neither has place, compare times (start lists have none, so lane)
         
         match (place_a, place_b) {
             (Some(pa), Some(pb)) => {
@@ -100,11 +733,173 @@ pub fn sort_results(results: &mut Vec<AthleteResult>) {
             },
             (Some(_), None) => std::cmp::Ordering::Less,
             (None, Some(_)) => std::cmp::Ordering::Greater,
-            (None, None) => lane_a.cmp(&lane_b),
+            (None, None) => match (&a.time_value, &b.time_value) {
+                (Some(ta), Some(tb)) if ta != tb => ta.cmp(tb),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                _ => lane_a.cmp(&lane_b),
+            },
         }
     });
+
+    // Gaps to the leader
+    let leader = results.iter()
+        .filter_map(|r| r.time_value.as_ref())
+        .filter(|t| t.ms.is_some())
+        .min()
+        .cloned();
+    for r in results.iter_mut() {
+        r.gap = match (&r.time_value, &leader) {
+            (Some(t), Some(l)) => match t.diff(l) {
+                Some(diff) if diff > 0 => format_gap(diff, t.precision.max(l.precision)),
+                _ => String::new(),
+            },
+            _ => String::new(),
+        };
+    }
 }
 
 
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(s: &mut ScoreboardState, ms: u64) {
+        let time = format!("{}.{:02}", ms / 1000, ms % 1000 / 10);
+        s.apply(&LynxEvent::ClockTick { time, ms: Some(ms), precision: 2 });
+    }
+
+    fn stopped(s: &mut ScoreboardState, ms: u64) {
+        let time = format!("{}.{:02}", ms / 1000, ms % 1000 / 10);
+        s.apply(&LynxEvent::TimeStopped { time, ms: Some(ms) });
+    }
+
+    #[test]
+    fn manual_start_unfreezes_clock() {
+        let mut s = ScoreboardState::default();
+        tick(&mut s, 10_000);
+        stopped(&mut s, 10_230);
+        tick(&mut s, 11_000);
+        assert!(s.clock_frozen);
+        assert_eq!(s.time, "10.23");
+
+        // No gun, the clock just starts over
+        tick(&mut s, 100);
+        assert!(!s.clock_frozen);
+        assert_eq!(s.time, "0.10");
+    }
+
+    #[test]
+    fn manual_start_is_a_new_race() {
+        let mut s = ScoreboardState::default();
+        tick(&mut s, 500);
+        assert_eq!(s.phase, RacePhase::Running);
+        stopped(&mut s, 10_230);
+        s.apply(&LynxEvent::WindMeasured { reading: WindReading::from_value(1.2) });
+        assert_eq!(s.phase, RacePhase::Stopped);
+        assert!(!s.running);
+
+        tick(&mut s, 12_000);
+        assert_eq!(s.phase, RacePhase::Stopped);
+
+        tick(&mut s, 0);
+        assert_eq!(s.phase, RacePhase::Running);
+        assert!(s.running);
+        assert!(s.finish_time.is_empty());
+        assert!(s.wind_reading.is_none());
+    }
+
+    fn header(s: &mut ScoreboardState, race_id: &str) {
+        s.apply(&LynxEvent::HeaderReceived {
+            header: String::new(),
+            event_name: "Women 100m".to_string(),
+            event_number: race_id.to_string(),
+            info: HeaderInfo { status: Some(ResultStatus::Unofficial), race_id: race_id.to_string(), ..Default::default() },
+        });
+    }
+
+    fn result(s: &mut ScoreboardState, lane: &str) {
+        let result = AthleteResult { place: "1".to_string(), lane: lane.to_string(), ..Default::default() };
+        s.apply(&LynxEvent::ResultUpserted { result: Box::new(result) });
+    }
+
+    #[test]
+    fn next_race_stays_out_of_previous_history() {
+        let mut s = ScoreboardState::default();
+        tick(&mut s, 9_000);
+        stopped(&mut s, 11_500);
+        header(&mut s, "1-1-01");
+        result(&mut s, "4");
+        assert_eq!(s.races["1-1-01"].finish_time, "11.50");
+
+        // Next heat, its header only comes with the results
+        s.apply(&LynxEvent::GunStart { time: "12:00:00.0".to_string(), ms: None });
+        s.apply(&LynxEvent::WindMeasured { reading: WindReading::from_value(-0.4) });
+        s.apply(&LynxEvent::SplitUpdated { index: 1, name: Some("50m".to_string()), time: Some("6.10".to_string()) });
+        stopped(&mut s, 11_800);
+        let previous = &s.races["1-1-01"];
+        assert_eq!(previous.finish_time, "11.50");
+        assert!(previous.gun_time.is_empty());
+        assert!(previous.wind_reading.is_none());
+        assert!(previous.splits.is_empty());
+
+        header(&mut s, "1-1-02");
+        result(&mut s, "3");
+        let race = &s.races["1-1-02"];
+        assert_eq!(race.finish_time, "11.80");
+        assert_eq!(race.gun_time, "12:00:00.0");
+        assert_eq!(race.wind_reading.as_ref().map(|w| w.value), Some(-0.4));
+        assert_eq!(race.splits.len(), 1);
+
+        // Later changes to the current race go straight in
+        stopped(&mut s, 11_790);
+        assert_eq!(s.races["1-1-02"].finish_time, "11.79");
+    }
+
+    #[test]
+    fn migrates_event_number_keys() {
+        let old: IndexMap<String, RaceData> = serde_json::from_str(include_str!("../events-history.json")).unwrap();
+        assert!(old.contains_key("13C"));
+
+        let (races, migrated) = migrate_history(old);
+        assert!(migrated);
+        assert!(!races.contains_key("13C"));
+        let race = &races["13C-1-01"];
+        assert_eq!(race.event_name, "M 1500 (111) Qual 13C");
+        assert_eq!(race.info, HeaderInfo {
+            status: Some(ResultStatus::Unofficial),
+            wind: "nwi".to_string(),
+            round: Some(1),
+            heat: Some(1),
+            start_type: Some(StartType::Auto),
+            participants: Some(8),
+            race_id: "13C-1-01".to_string(),
+        });
+        assert_eq!(races["13A-1-01"].info.status, Some(ResultStatus::Official));
+
+        // Already migrated files are left alone
+        let (again, migrated) = migrate_history(races.clone());
+        assert!(!migrated);
+        assert_eq!(again.keys().collect::<Vec<_>>(), races.keys().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn migration_keeps_a_backup() {
+        let path = std::env::temp_dir().join(format!("lynx-vmix-history-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let original = include_str!("../events-history.json");
+        fs::write(path, original).unwrap();
+
+        let races = load_history(path);
+        assert!(races.contains_key("13C-1-01"));
+        let backup = format!("{}.bak", path);
+        assert_eq!(fs::read_to_string(&backup).unwrap(), original);
+        assert!(load_history(path).contains_key("13C-1-01"));
+
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(backup);
+    }
+}
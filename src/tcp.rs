@@ -1,42 +1,36 @@
+use crate::lss::LssDecoder;
 use crate::parser::LynxParser;
-use crate::state::SharedState;
-use tokio::net::TcpListener;
+use crate::events::EventBus;
+use crate::config::BridgeConfig;
+use crate::sources::SourceRegistry;
+use socket2::{SockRef, TcpKeepalive};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
 use tokio::io::AsyncReadExt;
-use log::{info, error};
+use log::{debug, info, warn, error};
 
-pub async fn start_listener(state: SharedState, port: u16) {
+pub async fn start_listener(
+    bus: EventBus,
+    sources: SourceRegistry,
+    decoder: Arc<LssDecoder>,
+    config: Arc<BridgeConfig>,
+    port: u16,
+) {
     let addr = format!("0.0.0.0:{}", port);
     let listener = TcpListener::bind(&addr).await.expect("Failed to bind TCP listener");
     info!("TCP Listener waiting for FinishLynx on {}", addr);
 
     loop {
         match listener.accept().await {
-            Ok((mut socket, addr)) => {
+            Ok((socket, addr)) => {
                 info!("Accepted connection from {}", addr);
-                let state_clone = state.clone();
-                
-                tokio::spawn(async move {
-                    let mut buf = [0u8; 1024];
-                    let mut parser = LynxParser::new(state_clone);
-                    
-                    loop {
-                        match socket.read(&mut buf).await {
-                            Ok(0) => {
-                                info!("Connection closed by {}", addr);
-                                break;
-                            }
-                            Ok(n) => {
-                                let chunk = &buf[0..n];
-                                log::debug!("Received {} bytes: {:02X?}", n, chunk);
-                                parser.process_chunk(chunk);
-                            }
-                            Err(e) => {
-                                error!("Socket error: {}", e);
-                                break;
-                            }
-                        }
-                    }
-                });
+                set_keepalive(&socket);
+                // Each connection parses on its own, the registry decides who drives the board
+                let source = sources.register("tcp", addr);
+                let parser = LynxParser::new(bus.clone(), source, decoder.clone(), config.clone());
+                tokio::spawn(read_stream(socket, addr, parser));
             }
             Err(e) => {
                 error!("Accept error: {}", e);
@@ -44,3 +38,71 @@ pub async fn start_listener(state: SharedState, port: u16) {
         }
     }
 }
+
+// For venues where Lynx is the server and the bridge can't take inbound
+// connections: dial out, and keep dialing when it drops
+pub async fn start_client(
+    bus: EventBus,
+    sources: SourceRegistry,
+    decoder: Arc<LssDecoder>,
+    config: Arc<BridgeConfig>,
+    addr: String,
+) {
+    info!("Connecting to FinishLynx at {}", addr);
+    let mut backoff = Duration::from_secs(1);
+    loop {
+        match TcpStream::connect(&addr).await {
+            Ok(socket) => {
+                let peer = socket.peer_addr().ok();
+                info!("Connected to FinishLynx at {}", addr);
+                backoff = Duration::from_secs(1);
+                set_keepalive(&socket);
+                if let Some(peer) = peer {
+                    let source = sources.register("tcp-out", peer);
+                    let parser = LynxParser::new(bus.clone(), source, decoder.clone(), config.clone());
+                    read_stream(socket, peer, parser).await;
+                }
+                info!("Lost connection to FinishLynx at {}, reconnecting", addr);
+            }
+            Err(e) => debug!("Could not reach FinishLynx at {}: {}", addr, e),
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(Duration::from_secs(30));
+    }
+}
+
+// A machine that drops off the network never closes the connection, and
+// Lynx is quiet between races, so silence alone proves nothing. Keepalive
+// probes fail the read after about 25 s instead of never.
+pub fn set_keepalive(socket: &TcpStream) {
+    let keepalive = TcpKeepalive::new()
+        .with_time(Duration::from_secs(10))
+        .with_interval(Duration::from_secs(5))
+        .with_retries(3);
+    if let Err(e) = SockRef::from(socket).set_tcp_keepalive(&keepalive) {
+        warn!("Could not enable TCP keepalive: {}", e);
+    }
+}
+
+// Feeds one connection into its parser until it closes
+async fn read_stream(mut socket: TcpStream, addr: SocketAddr, mut parser: LynxParser) {
+    let mut buf = [0u8; 1024];
+    loop {
+        match socket.read(&mut buf).await {
+            Ok(0) => {
+                info!("Connection closed by {} (source #{})", addr, parser.source().id());
+                break;
+            }
+            Ok(n) => {
+                let chunk = &buf[0..n];
+                parser.source().record_bytes(n);
+                log::debug!("Received {} bytes: {:02X?}", n, chunk);
+                parser.process_chunk(chunk);
+            }
+            Err(e) => {
+                error!("Socket error: {}", e);
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use serde::{Serialize, Deserialize};
+use std::time::Duration;
+
+// How the bridge renders times it gets as plain milliseconds (\11\02),
+// so the look is set here rather than in the Lynx script.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeFormat {
+    pub precision: u8, // Digits after the decimal point, 0-3
+    pub hours: HoursMode,
+    pub leading_zeros: bool, // "00:10.23" instead of "10.23"
+}
+
+impl Default for TimeFormat {
+    fn default() -> Self {
+        Self {
+            precision: 2,
+            hours: HoursMode::Auto,
+            leading_zeros: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HoursMode {
+    Auto,   // Only once the clock passes an hour
+    Always,
+    Never,  // Minutes keep counting past 59
+}
+
+impl TimeFormat {
+    // Fractions are truncated, like the Lynx running clock
+    pub fn format(&self, duration: Duration) -> String {
+        let precision = self.precision.min(3) as u32;
+        let total_ms = duration.as_millis() as u64;
+
+        let hours = total_ms / 3_600_000;
+        let minutes = (total_ms / 60_000) % 60;
+        let seconds = (total_ms / 1000) % 60;
+        let fraction = (total_ms % 1000) / 10u64.pow(3 - precision);
+
+        let show_hours = match self.hours {
+            HoursMode::Always => true,
+            HoursMode::Never => false,
+            HoursMode::Auto => hours > 0,
+        };
+        let total_minutes = if show_hours { minutes } else { hours * 60 + minutes };
+        let show_minutes = show_hours || total_minutes > 0 || self.leading_zeros;
+
+        let mut out = String::new();
+        if show_hours {
+            if self.leading_zeros {
+                out.push_str(&format!("{:02}:", hours));
+            } else {
+                out.push_str(&format!("{}:", hours));
+            }
+        }
+        if show_minutes {
+            if show_hours || self.leading_zeros {
+                out.push_str(&format!("{:02}:", total_minutes));
+            } else {
+                out.push_str(&format!("{}:", total_minutes));
+            }
+            out.push_str(&format!("{:02}", seconds));
+        } else {
+            out.push_str(&seconds.to_string());
+        }
+        if precision > 0 {
+            out.push_str(&format!(".{:0width$}", fraction, width = precision as usize));
+        }
+        out
+    }
+}
+
+// Binary time lines carry milliseconds as a 4 byte little endian integer
+pub fn from_binary(raw: &[u8]) -> Option<Duration> {
+    let bytes: [u8; 4] = raw.get(..4)?.try_into().ok()?;
+    Some(Duration::from_millis(u32::from_le_bytes(bytes) as u64))
+}
+
+// A time as Lynx printed it ("2:27.93", "12:16:03.0", "10.00", "DNF"),
+// parsed so we can sort and subtract. The raw text is kept for display.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RaceTime {
+    pub raw: String,
+    pub ms: Option<u64>,
+    pub status: Option<String>, // DNF, DNS, DQ, NT...
+    pub precision: u8, // Digits after the decimal point as sent
+}
+
+impl RaceTime {
+    pub fn parse(raw: &str) -> Option<Self> {
+        let text = raw.trim();
+        if text.is_empty() {
+            return None;
+        }
+
+        // Status codes instead of a time, maybe followed by the rule ("DQ R168.7")
+        let first = text.split_whitespace().next().unwrap_or_default();
+        if first.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Some(Self {
+                raw: raw.to_string(),
+                status: Some(first.to_uppercase()),
+                ..Default::default()
+            });
+        }
+
+        // Deltas come with a sign
+        let digits = text.trim_start_matches('+');
+        let parts: Vec<&str> = digits.split(':').collect();
+        if parts.len() > 3 {
+            return None;
+        }
+
+        let (seconds, fraction) = match parts[parts.len() - 1].split_once('.') {
+            Some((s, f)) => (s, f),
+            None => (parts[parts.len() - 1], ""),
+        };
+        if fraction.len() > 3 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let mut ms: u64 = 0;
+        for part in &parts[..parts.len() - 1] {
+            ms = (ms + part.parse::<u64>().ok()?) * 60;
+        }
+        ms = (ms + seconds.parse::<u64>().ok()?) * 1000;
+        if !fraction.is_empty() {
+            ms += fraction.parse::<u64>().ok()? * 10u64.pow(3 - fraction.len() as u32);
+        }
+
+        Some(Self {
+            raw: raw.to_string(),
+            ms: Some(ms),
+            status: None,
+            precision: fraction.len() as u8,
+        })
+    }
+
+    // How far behind `leader` this is, in ms (negative if ahead)
+    pub fn diff(&self, leader: &RaceTime) -> Option<i64> {
+        Some(self.ms? as i64 - leader.ms? as i64)
+    }
+
+    // Re-render with a different precision, statuses come back unchanged
+    pub fn format(&self, format: &TimeFormat) -> String {
+        match self.ms {
+            Some(ms) => format.format(Duration::from_millis(ms)),
+            None => self.raw.trim().to_string(),
+        }
+    }
+}
+
+// Times first (fastest first), then statuses
+impl Ord for RaceTime {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        match (self.ms, other.ms) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => self.status.cmp(&other.status),
+        }
+    }
+}
+
+impl PartialOrd for RaceTime {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for RaceTime {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for RaceTime {}
+
+// "+1.23" style gap, at the given precision
+pub fn format_gap(diff_ms: i64, precision: u8) -> String {
+    let format = TimeFormat { precision, ..Default::default() };
+    let sign = if diff_ms < 0 { "-" } else { "+" };
+    format!("{}{}", sign, format.format(Duration::from_millis(diff_ms.unsigned_abs())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(raw: &str) -> Option<u64> {
+        RaceTime::parse(raw).and_then(|t| t.ms)
+    }
+
+    fn format(ms: u64, precision: u8, hours: HoursMode, leading_zeros: bool) -> String {
+        TimeFormat { precision, hours, leading_zeros }.format(Duration::from_millis(ms))
+    }
+
+    #[test]
+    fn parses_times() {
+        assert_eq!(ms("2:27.93"), Some(147_930));
+        assert_eq!(ms("12:16:03.0"), Some(44_163_000));
+        assert_eq!(ms("10.00"), Some(10_000));
+        assert_eq!(ms(" 10.123 "), Some(10_123));
+        assert_eq!(ms("+0.05"), Some(50));
+        assert_eq!(RaceTime::parse("2:27.93").unwrap().precision, 2);
+        assert_eq!(RaceTime::parse("12:16:03.0").unwrap().precision, 1);
+        assert!(RaceTime::parse("").is_none());
+        assert!(RaceTime::parse("1:2:3:4").is_none());
+        assert!(RaceTime::parse("10.1234").is_none());
+    }
+
+    #[test]
+    fn parses_statuses() {
+        let dnf = RaceTime::parse("DNF").unwrap();
+        assert_eq!((dnf.ms, dnf.status.as_deref()), (None, Some("DNF")));
+
+        let dq = RaceTime::parse("DQ R168.7").unwrap();
+        assert_eq!((dq.ms, dq.status.as_deref()), (None, Some("DQ")));
+        assert_eq!(dq.format(&TimeFormat::default()), "DQ R168.7");
+    }
+
+    #[test]
+    fn orders_times_then_statuses() {
+        let mut times: Vec<RaceTime> = ["DQ R168.7", "2:27.93", "DNF", "59.99", "1:00.00"]
+            .iter()
+            .filter_map(|raw| RaceTime::parse(raw))
+            .collect();
+        times.sort();
+        let raw: Vec<&str> = times.iter().map(|t| t.raw.as_str()).collect();
+        assert_eq!(raw, ["59.99", "1:00.00", "2:27.93", "DNF", "DQ R168.7"]);
+        assert_eq!(RaceTime::parse("10.00"), RaceTime::parse("10.000"));
+    }
+
+    #[test]
+    fn formats_by_precision() {
+        assert_eq!(format(10_129, 0, HoursMode::Auto, false), "10");
+        assert_eq!(format(10_129, 1, HoursMode::Auto, false), "10.1");
+        assert_eq!(format(10_129, 2, HoursMode::Auto, false), "10.12");
+        assert_eq!(format(10_129, 3, HoursMode::Auto, false), "10.129");
+        assert_eq!(format(10_129, 9, HoursMode::Auto, false), "10.129");
+    }
+
+    #[test]
+    fn formats_by_hours_mode() {
+        let under_an_hour = 147_930;
+        let over_an_hour = 3_600_000 + 147_930;
+
+        assert_eq!(format(under_an_hour, 2, HoursMode::Auto, false), "2:27.93");
+        assert_eq!(format(over_an_hour, 2, HoursMode::Auto, false), "1:02:27.93");
+        assert_eq!(format(under_an_hour, 2, HoursMode::Always, false), "0:02:27.93");
+        assert_eq!(format(over_an_hour, 2, HoursMode::Never, false), "62:27.93");
+        assert_eq!(format(under_an_hour, 2, HoursMode::Never, false), "2:27.93");
+
+        assert_eq!(format(10_120, 2, HoursMode::Auto, true), "00:10.12");
+        assert_eq!(format(over_an_hour, 2, HoursMode::Always, true), "01:02:27.93");
+    }
+
+    #[test]
+    fn formats_gaps() {
+        assert_eq!(format_gap(50, 2), "+0.05");
+        assert_eq!(format_gap(-1_230, 2), "-1.23");
+    }
+}
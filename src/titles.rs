@@ -0,0 +1,199 @@
+use crate::pages;
+use crate::state::ScoreboardState;
+use crate::vmix;
+use indexmap::IndexMap;
+use log::{info, warn};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+
+pub const TITLES_FILE: &str = "vmix-titles.json";
+
+// Which title fields get which scoreboard values. The file is
+// input (name, number or GUID) -> title field -> source expression:
+//
+// {
+//   "Scoreboard": { "Time.Text": "clock", "Event.Text": "event_name" },
+//   "Results":    { "Name1.Text": "results[0].name", "Time1.Text": "results[0].time" },
+//   "Lower":      { "Name1.Text": "page[0].name", "Page.Text": "page" }
+// }
+//
+// It is re-read whenever it changes on disk.
+#[derive(Debug, Clone)]
+pub struct TitleBinding {
+    pub input: String,
+    pub field: String, // SelectedName
+    pub source: Source,
+}
+
+pub type Titles = Arc<Vec<TitleBinding>>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Field(String), // Anything in /vmix/live.xml, plus "clock"
+    Result { index: usize, field: String },
+    PageRow { index: usize, field: String }, // Row on the current results page
+    Message(usize),
+    Split { index: usize, field: String },
+}
+
+fn expression_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"^([a-z_0-9]+)(?:\[(\d+)\](?:\.([a-z_]+))?)?$").unwrap())
+}
+
+impl Source {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let expr = expr.trim();
+        let caps = expression_regex().captures(expr)
+            .ok_or_else(|| format!("can't read source expression '{}'", expr))?;
+        let name = &caps[1];
+        let Some(index) = caps.get(2).and_then(|m| m.as_str().parse::<usize>().ok()) else {
+            // A typo would quietly blank the title field
+            if !field_exists(name) {
+                return Err(format!("no scoreboard field '{}'", name));
+            }
+            return Ok(Source::Field(name.to_string()));
+        };
+        let field = caps.get(3).map(|m| m.as_str().to_string());
+
+        match (name, field) {
+            ("results" | "page", Some(field)) => {
+                if !vmix::result_fields(&Default::default()).iter().any(|(f, _)| *f == field) {
+                    return Err(format!("results have no field '{}'", field));
+                }
+                if name == "page" {
+                    Ok(Source::PageRow { index, field })
+                } else {
+                    Ok(Source::Result { index, field })
+                }
+            }
+            ("messages", None) => Ok(Source::Message(index)),
+            ("splits", Some(field)) if matches!(field.as_str(), "name" | "time") => Ok(Source::Split { index, field }),
+            _ => Err(format!("can't read source expression '{}'", expr)),
+        }
+    }
+
+    // `live` is vmix::live_fields, worked out once per update rather than per title
+    pub fn resolve(&self, s: &ScoreboardState, live: &HashMap<String, String>) -> String {
+        match self {
+            Source::Field(name) => {
+                let name = if name == "clock" { "time" } else { name.as_str() };
+                live.get(name).cloned().unwrap_or_default()
+            }
+            Source::Result { index, field } => result_field(s, *index, field),
+            Source::PageRow { index, field } => {
+                // Past the end of the page is someone else's row
+                if s.page_size > 0 && *index >= s.page_size {
+                    return String::new();
+                }
+                result_field(s, pages::current_offset(s) + index, field)
+            }
+            Source::Message(index) => s.messages.get(*index).cloned().unwrap_or_default(),
+            Source::Split { index, field } => s.splits.get(*index)
+                .map(|split| if field == "name" { split.name.clone() } else { split.time.clone() })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+// Names in /vmix/live.xml, message_1... only show up once there are messages
+fn field_exists(name: &str) -> bool {
+    name == "clock"
+        || vmix::live_fields(&Default::default()).iter().any(|(field, _)| field == name)
+        || name.strip_prefix("message_").and_then(|n| n.parse::<usize>().ok()).is_some_and(|n| n >= 1)
+}
+
+fn result_field(s: &ScoreboardState, index: usize, field: &str) -> String {
+    s.results.get(index)
+        .and_then(|r| vmix::result_fields(r).iter().find(|(f, _)| *f == field).map(|(_, v)| v.to_string()))
+        .unwrap_or_default()
+}
+
+pub fn load_titles(path: &str) -> Result<Vec<TitleBinding>, String> {
+    if !Path::new(path).exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let map: IndexMap<String, IndexMap<String, String>> = serde_json::from_str(&content)
+        .map_err(|e| e.to_string())?;
+
+    let mut titles = Vec::new();
+    for (input, fields) in map {
+        for (field, expr) in fields {
+            let source = Source::parse(&expr).map_err(|e| format!("{} / {}: {}", input, field, e))?;
+            titles.push(TitleBinding { input: input.clone(), field, source });
+        }
+    }
+    Ok(titles)
+}
+
+pub fn channel() -> (watch::Sender<Titles>, watch::Receiver<Titles>) {
+    let titles = match load_titles(TITLES_FILE) {
+        Ok(titles) => {
+            info!("Loaded {} title fields from {}", titles.len(), TITLES_FILE);
+            titles
+        }
+        Err(e) => {
+            warn!("Invalid {}: {}", TITLES_FILE, e);
+            Vec::new()
+        }
+    };
+    watch::channel(Arc::new(titles))
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Designers edit the file while we're live, pick the changes up. A broken
+// file keeps the last good mapping.
+pub async fn watch_file(tx: watch::Sender<Titles>) {
+    let mut last = modified(TITLES_FILE);
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+        let current = modified(TITLES_FILE);
+        if current == last {
+            continue;
+        }
+        last = current;
+        match load_titles(TITLES_FILE) {
+            Ok(titles) => {
+                info!("Reloaded {} title fields from {}", titles.len(), TITLES_FILE);
+                tx.send_replace(Arc::new(titles));
+            }
+            Err(e) => warn!("Invalid {}, keeping the previous mapping: {}", TITLES_FILE, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_fields() {
+        for name in ["clock", "time", "event_name", "wind_legal", "message_2"] {
+            assert_eq!(Source::parse(name), Ok(Source::Field(name.to_string())));
+        }
+    }
+
+    #[test]
+    fn misspelt_field() {
+        assert_eq!(Source::parse("evnt_name"), Err("no scoreboard field 'evnt_name'".to_string()));
+        assert!(Source::parse("message_0").is_err());
+    }
+
+    #[test]
+    fn indexed_sources() {
+        assert_eq!(Source::parse("results[0].name"), Ok(Source::Result { index: 0, field: "name".to_string() }));
+        assert_eq!(Source::parse("page[2].time"), Ok(Source::PageRow { index: 2, field: "time".to_string() }));
+        assert_eq!(Source::parse("messages[1]"), Ok(Source::Message(1)));
+        assert!(Source::parse("results[0].nmae").is_err());
+    }
+}
@@ -0,0 +1,81 @@
+use crate::lss::LssDecoder;
+use crate::parser::LynxParser;
+use crate::events::EventBus;
+use crate::config::BridgeConfig;
+use crate::sources::SourceRegistry;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use log::{info, error};
+
+// No disconnects over UDP, a sender that's quiet this long is gone
+const SOURCE_IDLE: Duration = Duration::from_secs(30);
+// Pieces of a frame further apart than this don't belong together
+const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+struct Peer {
+    parser: LynxParser,
+    last_seen: Instant,
+}
+
+// Scoreboard output over UDP, one parser (and source) per sender. Without
+// `reassemble` every datagram is taken as whole frames and leftovers are
+// dropped; with it, datagrams are joined up like a TCP stream.
+pub async fn start_listener(
+    bus: EventBus,
+    sources: SourceRegistry,
+    decoder: Arc<LssDecoder>,
+    config: Arc<BridgeConfig>,
+    port: u16,
+    reassemble: bool,
+) {
+    let addr = format!("0.0.0.0:{}", port);
+    let socket = UdpSocket::bind(&addr).await.expect("Failed to bind UDP socket");
+    info!("UDP Listener waiting for FinishLynx on {}", addr);
+
+    let mut peers: HashMap<SocketAddr, Peer> = HashMap::new();
+    let mut buf = vec![0u8; 65536];
+    let mut sweep = tokio::time::interval(Duration::from_secs(5));
+
+    loop {
+        tokio::select! {
+            recv = socket.recv_from(&mut buf) => match recv {
+                Ok((n, addr)) => {
+                    let peer = peers.entry(addr).or_insert_with(|| {
+                        info!("UDP datagrams from {}", addr);
+                        let source = sources.register("udp", addr);
+                        Peer {
+                            parser: LynxParser::new(bus.clone(), source, decoder.clone(), config.clone()),
+                            last_seen: Instant::now(),
+                        }
+                    });
+                    if reassemble && peer.last_seen.elapsed() > REASSEMBLY_TIMEOUT {
+                        peer.parser.discard_partial();
+                    }
+                    peer.last_seen = Instant::now();
+
+                    let chunk = &buf[0..n];
+                    log::debug!("Received {} bytes from {}: {:02X?}", n, addr, chunk);
+                    peer.parser.source().record_bytes(n);
+                    peer.parser.process_chunk(chunk);
+                    if !reassemble {
+                        peer.parser.discard_partial();
+                    }
+                }
+                // Windows reports ICMP port unreachable here, nothing to do about it
+                Err(e) => error!("UDP receive error: {}", e),
+            },
+            _ = sweep.tick() => {
+                peers.retain(|addr, peer| {
+                    let alive = peer.last_seen.elapsed() < SOURCE_IDLE;
+                    if !alive {
+                        info!("UDP sender {} went quiet", addr);
+                    }
+                    alive
+                });
+            }
+        }
+    }
+}
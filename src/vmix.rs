@@ -0,0 +1,154 @@
+use crate::pages;
+use crate::rules::Rule;
+use crate::state::{AthleteResult, ScoreboardState};
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VmixConfig {
+    pub fixed_rows: usize, // Rows in /vmix/results-fixed.xml unless ?rows= says otherwise
+    pub api: VmixApiConfig, // Title fields to push come from vmix-titles.json
+    pub rules: Vec<Rule>, // Fired over the API, see rules.rs
+}
+
+impl Default for VmixConfig {
+    fn default() -> Self {
+        Self {
+            fixed_rows: 8,
+            api: VmixApiConfig::default(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VmixApiConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub clock_interval_ms: u64, // At most one clock update per this many ms
+}
+
+impl Default for VmixApiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "127.0.0.1".to_string(),
+            port: 8099,
+            clock_interval_ms: 100,
+        }
+    }
+}
+
+// Data Source XML for vMix. vMix wants repeating elements it can pick rows
+// out of with an XPath, so everything is flat text elements:
+//   /vmix/live.xml     -> /scoreboard (one row)
+//   /vmix/results.xml  -> /results/athlete (one row per athlete)
+//   /vmix/results-page.xml?page=2 -> the same, one page of them
+
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c if c.is_control() && c != '\n' && c != '\t' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn push_field(xml: &mut String, indent: &str, name: &str, value: &str) {
+    xml.push_str(&format!("{}<{}>{}</{}>\n", indent, name, escape(value), name));
+}
+
+// Scoreboard values by name, shared by live.xml and the title mapping
+pub fn live_fields(s: &ScoreboardState) -> Vec<(String, String)> {
+    let clock = if s.clock_time.is_empty() { &s.time } else { &s.clock_time };
+    let (wind, wind_legal) = match &s.wind_reading {
+        Some(w) => (w.display.clone(), w.legal.map(|l| l.to_string()).unwrap_or_default()),
+        None => (s.info.wind.clone(), String::new()),
+    };
+    let opt = |v: Option<u32>| v.map(|n| n.to_string()).unwrap_or_default();
+
+    let mut fields: Vec<(String, String)> = [
+        ("time", clock.clone()),
+        ("lynx_time", s.time.clone()),
+        ("running", s.running.to_string()),
+        ("phase", format!("{:?}", s.phase).to_uppercase()),
+        ("gun_time", s.gun_time.clone()),
+        ("finish_time", s.finish_time.clone()),
+        ("time_of_day", s.time_of_day.clone()),
+        ("event_name", s.event_name.clone()),
+        ("event_number", s.event_number.clone()),
+        ("status", s.info.status.map(|st| format!("{:?}", st).to_uppercase()).unwrap_or_default()),
+        ("round", opt(s.info.round)),
+        ("heat", opt(s.info.heat)),
+        ("participants", opt(s.info.participants)),
+        ("race_id", s.info.race_id.clone()),
+        ("wind", wind),
+        ("wind_legal", wind_legal),
+        ("message", s.messages.join("\n")),
+        ("page", (pages::clamp_page(s, s.page, s.page_size) + 1).to_string()),
+        ("pages", pages::page_count(s.results.len(), s.page_size).to_string()),
+    ].into_iter().map(|(k, v)| (k.to_string(), v)).collect();
+    fields.extend(s.messages.iter()
+        .enumerate()
+        .map(|(i, m)| (format!("message_{}", i + 1), m.clone())));
+    fields
+}
+
+pub fn live_xml(s: &ScoreboardState) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<scoreboard>\n");
+    for (name, value) in live_fields(s) {
+        push_field(&mut xml, "  ", &name, &value);
+    }
+    xml.push_str("</scoreboard>\n");
+    xml
+}
+
+// Athletes from `offset` on. With `rows`, always exactly that many (blank
+// ones at the end) so title bindings stay put as results come in
+pub fn results_xml(s: &ScoreboardState, offset: usize, rows: Option<usize>) -> String {
+    let empty = AthleteResult::default();
+    let count = rows.unwrap_or(s.results.len().saturating_sub(offset));
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<results>\n");
+    for row in offset..offset + count {
+        let r = s.results.get(row).unwrap_or(&empty);
+        xml.push_str("  <athlete>\n");
+        push_field(&mut xml, "    ", "row", &(row + 1).to_string());
+        for (name, value) in result_fields(r) {
+            push_field(&mut xml, "    ", name, value);
+        }
+        xml.push_str("  </athlete>\n");
+    }
+    xml.push_str("</results>\n");
+    xml
+}
+
+pub fn result_fields(r: &AthleteResult) -> [(&'static str, &str); 16] {
+    [
+        ("place", &r.place),
+        ("lane", &r.lane),
+        ("id", &r.id),
+        ("name", &r.name),
+        ("affiliation", &r.affiliation),
+        ("time", &r.time),
+        ("delta_time", &r.delta_time),
+        ("gap", &r.gap),
+        ("cumulative_split", &r.cumulative_split),
+        ("last_split", &r.last_split),
+        ("laps_to_go", &r.laps_to_go),
+        ("license", &r.license),
+        ("reaction_time", &r.reaction_time),
+        ("speed", &r.speed),
+        ("pace", &r.pace),
+        ("best_split", &r.best_split),
+    ]
+}
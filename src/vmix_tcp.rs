@@ -0,0 +1,187 @@
+use crate::config::BridgeConfig;
+use crate::state::{SharedState, Topic};
+use crate::titles::Titles;
+use crate::vmix;
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{mpsc, watch};
+
+// Pushes mapped scoreboard fields into vMix titles over the vMix TCP API
+// (port 8099), instead of vMix polling a data source. `commands` are extra
+// functions to run as-is, e.g. from the rules.
+pub async fn start_client(
+    state: SharedState,
+    config: Arc<BridgeConfig>,
+    titles: watch::Receiver<Titles>,
+    mut commands: mpsc::UnboundedReceiver<String>,
+) {
+    let api = &config.vmix.api;
+    if !api.enabled {
+        return;
+    }
+    if titles.borrow().is_empty() {
+        warn!("vMix TCP API enabled but no titles are mapped in {}", crate::titles::TITLES_FILE);
+    }
+
+    let addr = format!("{}:{}", api.host, api.port);
+    let mut backoff = Duration::from_secs(1);
+    loop {
+        match TcpStream::connect(&addr).await {
+            Ok(stream) => {
+                info!("Connected to vMix at {}", addr);
+                backoff = Duration::from_secs(1);
+                // vMix only answers what we send, a dead PC would go unnoticed
+                crate::tcp::set_keepalive(&stream);
+                // Anything fired while we were away is stale by now
+                while let Ok(command) = commands.try_recv() {
+                    debug!("Dropping vMix command from while disconnected: {}", command.trim_end());
+                }
+                run_connection(stream, &state, &config, titles.clone(), &mut commands).await;
+                warn!("Lost connection to vMix at {}", addr);
+            }
+            Err(e) => debug!("Could not reach vMix at {}: {}", addr, e),
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(Duration::from_secs(10));
+    }
+}
+
+async fn run_connection(
+    stream: TcpStream,
+    state: &SharedState,
+    config: &BridgeConfig,
+    mut titles: watch::Receiver<Titles>,
+    commands: &mut mpsc::UnboundedReceiver<String>,
+) {
+    let (mut reader, mut writer) = stream.into_split();
+    let mut changes = state.read().changes.subscribe();
+    let mut sent: HashMap<(String, String), String> = HashMap::new();
+
+    // vMix may have been restarted, so start with everything
+    let current = titles.borrow_and_update().clone();
+    if sync(&mut writer, state, &current, &mut sent).await.is_err() {
+        return;
+    }
+
+    let mut clock_tick = tokio::time::interval(Duration::from_millis(config.vmix.api.clock_interval_ms.max(1)));
+    // Only polled while the clock has changed, so after a quiet spell it is
+    // far behind. Burst would then send the clock back to back.
+    clock_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut clock_pending = false;
+    let mut buf = [0u8; 1024];
+
+    loop {
+        tokio::select! {
+            change = changes.recv() => {
+                match change {
+                    // The clock changes many times a second, send it at our own pace
+                    Ok(Topic::Clock) => { clock_pending = true; continue; }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return,
+                }
+                let current = titles.borrow().clone();
+                if sync(&mut writer, state, &current, &mut sent).await.is_err() {
+                    return;
+                }
+            }
+            _ = clock_tick.tick(), if clock_pending => {
+                clock_pending = false;
+                let current = titles.borrow().clone();
+                if sync(&mut writer, state, &current, &mut sent).await.is_err() {
+                    return;
+                }
+            }
+            // New mapping, fields may now point elsewhere so send them all again
+            Ok(()) = titles.changed() => {
+                sent.clear();
+                let current = titles.borrow_and_update().clone();
+                if sync(&mut writer, state, &current, &mut sent).await.is_err() {
+                    return;
+                }
+            }
+            Some(command) = commands.recv() => {
+                debug!("vMix <- {}", command.trim_end());
+                if writer.write_all(command.as_bytes()).await.is_err() {
+                    return;
+                }
+            }
+            read = reader.read(&mut buf) => match read {
+                Ok(0) | Err(_) => return,
+                Ok(n) => {
+                    // "FUNCTION OK Completed" / "FUNCTION ER <reason>"
+                    for line in String::from_utf8_lossy(&buf[..n]).lines() {
+                        if line.starts_with("FUNCTION ER") {
+                            warn!("vMix: {}", line);
+                        } else {
+                            debug!("vMix: {}", line);
+                        }
+                    }
+                }
+            },
+        }
+    }
+}
+
+// Sends the titles whose value differs from what vMix last got
+async fn sync(
+    writer: &mut OwnedWriteHalf,
+    state: &SharedState,
+    titles: &Titles,
+    sent: &mut HashMap<(String, String), String>,
+) -> std::io::Result<()> {
+    let commands: Vec<String> = {
+        let s = state.read();
+        let live: HashMap<String, String> = vmix::live_fields(&s).into_iter().collect();
+        titles.iter()
+            .filter_map(|title| {
+                let value = title.source.resolve(&s, &live);
+                let key = (title.input.clone(), title.field.clone());
+                if sent.get(&key) == Some(&value) {
+                    return None;
+                }
+                sent.insert(key, value.clone());
+                Some(set_text(&title.input, &title.field, &value))
+            })
+            .collect()
+    };
+
+    for command in commands {
+        debug!("vMix <- {}", command.trim_end());
+        writer.write_all(command.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+pub fn set_text(input: &str, field: &str, value: &str) -> String {
+    function("SetText", &[("Input", input), ("SelectedName", field), ("Value", value)])
+}
+
+// FUNCTION <name> Key=value&...
+pub fn function(name: &str, params: &[(&str, &str)]) -> String {
+    let query: Vec<String> = params.iter()
+        .map(|(key, value)| format!("{}={}", key, url_encode(value)))
+        .collect();
+    if query.is_empty() {
+        format!("FUNCTION {}\r\n", name)
+    } else {
+        format!("FUNCTION {} {}\r\n", name, query.join("&"))
+    }
+}
+
+// vMix reads the query like a URL, so &, = and friends have to be escaped
+pub fn url_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
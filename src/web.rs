@@ -1,18 +1,57 @@
-use crate::state::SharedState;
+use crate::channels::Channel;
+use crate::config::BridgeConfig;
+use crate::pages;
+use crate::sources::{SourceRegistry, SourceRole};
+use crate::sse::SseHub;
+use crate::vmix;
+use crate::state::{ScoreboardState, SharedState, Topic};
 use axum::{
-    extract::{State, Json},
-    response::Html,
-    routing::get,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        FromRef, Path, Query, State, Json,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html, IntoResponse, Response,
+    },
+    routing::{get, post, MethodRouter},
     Router,
 };
-use serde_json::Value;
+use futures_util::stream::Stream;
+use std::convert::Infallible;
+use std::sync::Arc;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::broadcast::error::RecvError;
 
-pub async fn start_server(state: SharedState, port: u16) {
-    let app = Router::new()
-        .route("/", get(index))
-        .route("/live", get(get_live))
-        .route("/races", get(get_races))
-        .with_state(state);
+#[derive(Clone)]
+pub struct AppState {
+    pub state: SharedState,
+    pub sse: SseHub,
+    pub config: Arc<BridgeConfig>,
+    pub sources: SourceRegistry,
+    // Why the Lynx listeners are down, if they are
+    pub script_error: Option<String>,
+}
+
+impl FromRef<AppState> for SharedState {
+    fn from_ref(app: &AppState) -> Self {
+        app.state.clone()
+    }
+}
+
+pub async fn start_server(channels: Vec<Channel>, config: Arc<BridgeConfig>, script_error: Option<String>, port: u16) {
+    // The first channel keeps the old URLs, every channel also gets /<name>/...
+    let mut app = channel_router(&channels[0], &config, &script_error);
+    for channel in &channels {
+        app = app.nest(&format!("/{}", channel.name), channel_router(channel, &config, &script_error));
+    }
+    let list: Vec<Value> = channels.iter()
+        .map(|c| serde_json::json!({ "name": c.name, "port": c.port, "udp_port": c.udp_port, "connect": c.connect, "path": format!("/{}", c.name) }))
+        .collect();
+    let app = app.route("/channels", get(move || async move { Json(Value::Array(list)) }));
 
     let addr = format!("0.0.0.0:{}", port);
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
@@ -20,6 +59,55 @@ pub async fn start_server(state: SharedState, port: u16) {
     axum::serve(listener, app).await.unwrap();
 }
 
+fn channel_router(channel: &Channel, config: &Arc<BridgeConfig>, script_error: &Option<String>) -> Router {
+    let app_state = AppState {
+        sse: SseHub::start(channel.state.clone()),
+        state: channel.state.clone(),
+        config: config.clone(),
+        sources: channel.sources.clone(),
+        script_error: script_error.clone(),
+    };
+
+    channel_routes()
+        .into_iter()
+        .fold(Router::new(), |router, (path, handler)| router.route(path, handler))
+        .with_state(app_state)
+}
+
+// Served at the root and under every /<name>/
+fn channel_routes() -> Vec<(&'static str, MethodRouter<AppState>)> {
+    vec![
+        ("/", get(index)),
+        ("/live", get(get_live)),
+        ("/status", get(get_status)),
+        ("/races", get(get_races)),
+        ("/results", get(get_results_page)),
+        ("/sources", get(get_sources)),
+        ("/sources/switches", get(get_source_switches)),
+        ("/sources/:id/role", post(set_source_role)),
+        ("/ws", get(ws_handler)),
+        ("/events", get(sse_handler)),
+        ("/vmix/live.xml", get(vmix_live)),
+        ("/vmix/results.xml", get(vmix_results)),
+        ("/vmix/results-fixed.xml", get(vmix_results_fixed)),
+        ("/vmix/results-page.xml", get(vmix_results_page)),
+    ]
+}
+
+// Path segments taken at the root, a channel nested under one of them
+// would clash with the first channel's routes
+pub fn reserved_names() -> Vec<&'static str> {
+    let mut names: Vec<&str> = channel_routes()
+        .iter()
+        .filter_map(|(path, _)| path.split('/').nth(1))
+        .filter(|name| !name.is_empty())
+        .chain(["channels"])
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    names
+}
+
 async fn index() -> Html<&'static str> {
     Html(r#"
     <!DOCTYPE html>
@@ -29,42 +117,115 @@ async fn index() -> Html<&'static str> {
         <style>
             body { font-family: monospace; background: #000; color: #0f0; padding: 20px; }
             h1 { border-bottom: 1px solid #333; }
-            #time, #gun-time { font-size: 4em; font-weight: bold; }
+            #script-error { display: none; background: #600; color: #fff; padding: 10px; }
+            #time, #gun-time, #phase { font-size: 4em; font-weight: bold; }
             table { width: 100%; border-collapse: collapse; margin-top: 20px; }
             th, td { border: 1px solid #333; padding: 5px; text-align: left; }
             th { background: #111; }
         </style>
         <script>
-            async function poll() {
+            let data = {};
+            // Same page serves every channel, /<name>/ or the root
+            const base = location.pathname.endsWith('/') ? location.pathname : location.pathname + '/';
+
+            function render() {
+                document.getElementById('time').innerText = data.clock_time || data.time || "--:--.--";
+                document.getElementById('gun-time').innerText = data.gun_time || "--:--.--";
+                document.getElementById('phase').innerText = (data.phase || "idle").toUpperCase();
+                
+                let tbody = document.getElementById('results');
+                tbody.innerHTML = '';
+                (data.results || []).forEach(r => {
+                    let row = `<tr>
+                        <td>${r.place}</td>
+                        <td>${r.lane}</td>
+                        <td>${r.id}</td>
+                        <td>${r.name}</td>
+                        <td>${r.affiliation}</td>
+                        <td>${r.time}</td>
+                        <td>${r.delta_time}</td>
+                        <td>${r.gap}</td>
+                        <td>${r.last_split}</td>
+                        <td>${r.laps_to_go}</td>
+                    </tr>`;
+                    tbody.innerHTML += row;
+                });
+            }
+
+            // Snapshot on connect, then only the parts that changed
+            function connect() {
+                let ws = new WebSocket(`ws://${location.host}${base}ws`);
+                ws.onmessage = (e) => {
+                    let msg = JSON.parse(e.data);
+                    if (msg.type === 'snapshot') data = {};
+                    Object.assign(data, msg.data);
+                    render();
+                };
+                ws.onclose = () => setTimeout(connect, 1000);
+            }
+            // Which Lynx PCs are connected, and which one drives the board
+            async function loadSources() {
                 try {
-                    let res = await fetch('/live');
-                    let json = await res.json();
-                    let data = json[0];
-                    
-                    document.getElementById('time').innerText = data.time || "--:--.--";
-                    document.getElementById('gun-time').innerText = data.gun_time || "--:--.--";
-                    
-                    let tbody = document.getElementById('results');
+                    let sources = await (await fetch(base + 'sources')).json();
+                    let switches = await (await fetch(base + 'sources/switches')).json();
+                    let tbody = document.getElementById('sources');
                     tbody.innerHTML = '';
-                    data.results.forEach(r => {
-                        let row = `<tr>
-                            <td>${r.place}</td>
-                            <td>${r.lane}</td>
-                            <td>${r.id}</td>
-                            <td>${r.name}</td>
-                            <td>${r.affiliation}</td>
-                            <td>${r.time}</td>
+                    sources.forEach(s => {
+                        let idle = ((Date.now() - s.last_activity) / 1000).toFixed(1);
+                        let lastTime = s.last_time_packet ? ((Date.now() - s.last_time_packet) / 1000).toFixed(1) + 's ago' : 'never';
+                        tbody.innerHTML += `<tr>
+                            <td>#${s.id} ${s.kind}</td>
+                            <td>${s.peer}</td>
+                            <td>${s.role}${s.active ? ' (ACTIVE)' : ''}</td>
+                            <td>${new Date(s.connected_at).toLocaleTimeString()}</td>
+                            <td>${s.bytes}</td>
+                            <td>${s.frames}</td>
+                            <td>${idle}s ago</td>
+                            <td>${lastTime}</td>
+                            <td><button onclick="setRole(${s.id}, 'primary')">Primary</button>
+                                <button onclick="setRole(${s.id}, 'backup')">Backup</button>
+                                <button onclick="setRole(${s.id}, 'ignored')">Ignore</button></td>
                         </tr>`;
-                        tbody.innerHTML += row;
                     });
-                } catch(e) { console.error(e); }
-                setTimeout(poll, 100);
+                    document.getElementById('switches').innerHTML = switches.map(w =>
+                        `<li>${new Date(w.at).toLocaleTimeString()}: ${w.from || 'none'} -> ${w.to || 'none'} (${w.reason})</li>`
+                    ).join('');
+                } catch (e) {}
+            }
+
+            // A broken .lss keeps the listeners down, say so instead of an idle board
+            async function loadStatus() {
+                try {
+                    let status = await (await fetch(base + 'status')).json();
+                    let banner = document.getElementById('script-error');
+                    banner.innerText = status.script_error
+                        ? `Scoreboard script error, not listening for FinishLynx: ${status.script_error}`
+                        : '';
+                    banner.style.display = status.script_error ? 'block' : 'none';
+                } catch (e) {}
+            }
+
+            async function setRole(id, role) {
+                await fetch(`${base}sources/${id}/role`, {
+                    method: 'POST',
+                    headers: { 'Content-Type': 'application/json' },
+                    body: JSON.stringify({ role }),
+                });
+                loadSources();
             }
-            window.onload = poll;
+
+            window.onload = () => {
+                document.getElementById('races-link').href = base + 'races';
+                connect();
+                loadStatus();
+                loadSources();
+                setInterval(loadSources, 2000);
+            };
         </script>
     </head>
     <body>
         <h1>Lynx vMix Bridge - Live</h1>
+        <div id="script-error"></div>
         <div style="display: flex; gap: 50px;">
             <div>
                 <h3>Running Time</h3>
@@ -74,6 +235,10 @@ async fn index() -> Html<&'static str> {
                 <h3>Gun Time</h3>
                 <div id="gun-time">--:--.--</div>
             </div>
+            <div>
+                <h3>Phase</h3>
+                <div id="phase">IDLE</div>
+            </div>
         </div>
         <table>
             <thead>
@@ -84,11 +249,34 @@ async fn index() -> Html<&'static str> {
                     <th>Name</th>
                     <th>Affiliation</th>
                     <th>Time</th>
+                    <th>Delta</th>
+                    <th>Gap</th>
+                    <th>Last Split</th>
+                    <th>Laps To Go</th>
                 </tr>
             </thead>
             <tbody id="results"></tbody>
         </table>
-        <p><a href="/races" style="color: #0f0">View Races JSON</a></p>
+        <h3>Sources</h3>
+        <table>
+            <thead>
+                <tr>
+                    <th>Source</th>
+                    <th>Peer</th>
+                    <th>Role</th>
+                    <th>Connected</th>
+                    <th>Bytes</th>
+                    <th>Frames</th>
+                    <th>Last Activity</th>
+                    <th>Last Time Packet</th>
+                    <th></th>
+                </tr>
+            </thead>
+            <tbody id="sources"></tbody>
+        </table>
+        <h3>Source Switches</h3>
+        <ul id="switches"></ul>
+        <p><a id="races-link" href="races" style="color: #0f0">View Races JSON</a></p>
     </body>
     </html>
     "#)
@@ -96,16 +284,201 @@ async fn index() -> Html<&'static str> {
 
 async fn get_live(State(state): State<SharedState>) -> Json<Value> {
     let s = state.read();
-    let response = serde_json::json!([{
-        "time": s.time,
-        "running": s.running,
-        "results": s.results,
-        "messages": s.messages,
-        "event_name": s.event_name,
-        "event_number": s.event_number,
-        "gun_time": s.gun_time
-    }]);
-    Json(response)
+    Json(Value::Array(vec![Value::Object(snapshot(&s, &Topic::ALL.into()))]))
+}
+
+// The fields each topic covers, /live is all of them together
+pub fn topic_fields(s: &ScoreboardState, topic: Topic) -> Value {
+    match topic {
+        Topic::Clock => serde_json::json!({
+            "time": s.time,
+            "time_ms": s.time_ms,
+            "clock_time": s.clock_time,
+            "clock_ms": s.clock_ms,
+            "running": s.running,
+            "gun_time": s.gun_time,
+            "gun_time_ms": s.gun_time_ms,
+            "finish_time": s.finish_time,
+            "finish_time_ms": s.finish_time_ms,
+            "time_of_day": s.time_of_day
+        }),
+        Topic::Phase => serde_json::json!({
+            "phase": s.phase,
+            "phase_since": s.phase_since,
+            "phase_times": s.phase_times,
+            "running": s.running
+        }),
+        Topic::Header => serde_json::json!({
+            "event_name": s.event_name,
+            "event_number": s.event_number,
+            "status": s.info.status,
+            "wind": s.info.wind,
+            "round": s.info.round,
+            "heat": s.info.heat,
+            "start_type": s.info.start_type,
+            "participants": s.info.participants,
+            "race_id": s.info.race_id
+        }),
+        Topic::Results => serde_json::json!({ "results": s.results }),
+        Topic::Messages => serde_json::json!({ "messages": s.messages }),
+        Topic::Wind => serde_json::json!({ "wind_reading": s.wind_reading }),
+        Topic::Splits => serde_json::json!({ "splits": s.splits }),
+        Topic::Page => serde_json::json!({ "page": pages::page_view(s, s.page, s.page_size) }),
+    }
+}
+
+fn snapshot(s: &ScoreboardState, topics: &HashSet<Topic>) -> Map<String, Value> {
+    let mut data = Map::new();
+    for topic in Topic::ALL.into_iter().filter(|t| topics.contains(t)) {
+        if let Value::Object(fields) = topic_fields(s, topic) {
+            data.extend(fields);
+        }
+    }
+    data
+}
+
+// "?topics=clock,phase", everything if not given
+fn parse_topics(list: &str) -> HashSet<Topic> {
+    list.split(',').filter_map(Topic::parse).collect()
+}
+
+#[derive(Deserialize)]
+struct SubscribeRequest {
+    subscribe: Vec<String>,
+}
+
+async fn ws_handler(
+    ws: WebSocketUpgrade,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<SharedState>,
+) -> Response {
+    let topics = match params.get("topics") {
+        Some(list) => parse_topics(list),
+        None => Topic::ALL.into(),
+    };
+    ws.on_upgrade(move |socket| handle_socket(socket, state, topics))
+}
+
+async fn handle_socket(mut socket: WebSocket, state: SharedState, mut topics: HashSet<Topic>) {
+    let mut changes = state.read().changes.subscribe();
+
+    if send_snapshot(&mut socket, &state, &topics).await.is_err() {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            change = changes.recv() => match change {
+                Ok(topic) => {
+                    if !topics.contains(&topic) { continue; }
+                    let msg = serde_json::json!({
+                        "type": "update",
+                        "topic": topic,
+                        "data": topic_fields(&state.read(), topic),
+                    });
+                    if socket.send(Message::Text(msg.to_string())).await.is_err() {
+                        break;
+                    }
+                }
+                // Too slow to keep up, start them over with the whole picture
+                Err(RecvError::Lagged(_)) => {
+                    if send_snapshot(&mut socket, &state, &topics).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Closed) => break,
+            },
+            msg = socket.recv() => match msg {
+                // {"subscribe": ["clock"]} switches topics on the fly
+                Some(Ok(Message::Text(text))) => {
+                    if let Ok(req) = serde_json::from_str::<SubscribeRequest>(&text) {
+                        topics = req.subscribe.iter().filter_map(|t| Topic::parse(t)).collect();
+                        if send_snapshot(&mut socket, &state, &topics).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+async fn send_snapshot(socket: &mut WebSocket, state: &SharedState, topics: &HashSet<Topic>) -> Result<(), axum::Error> {
+    let msg = serde_json::json!({
+        "type": "snapshot",
+        "topics": topics,
+        "data": snapshot(&state.read(), topics),
+    });
+    socket.send(Message::Text(msg.to_string())).await
+}
+
+// EventSource clients send Last-Event-ID when they reconnect
+async fn sse_handler(
+    State(app): State<AppState>,
+    headers: HeaderMap,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let last_event_id = headers.get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok());
+    Sse::new(app.sse.stream(app.state, last_event_id)).keep_alive(KeepAlive::default())
+}
+
+fn xml(body: String) -> Response {
+    ([(header::CONTENT_TYPE, "application/xml; charset=utf-8")], body).into_response()
+}
+
+async fn vmix_live(State(state): State<SharedState>) -> Response {
+    xml(vmix::live_xml(&state.read()))
+}
+
+async fn vmix_results(State(state): State<SharedState>) -> Response {
+    xml(vmix::results_xml(&state.read(), 0, None))
+}
+
+// "?rows=10" overrides vmix.fixed_rows from the config
+async fn vmix_results_fixed(
+    State(app): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let rows = params.get("rows")
+        .and_then(|r| r.parse::<usize>().ok())
+        .unwrap_or(app.config.vmix.fixed_rows);
+    xml(vmix::results_xml(&app.state.read(), 0, Some(rows)))
+}
+
+// "?page=2&size=8", pages count from 1. Without a page it's whichever one
+// the rotation is on, without a size it's pages.size from the config.
+fn page_params(s: &ScoreboardState, config: &BridgeConfig, params: &HashMap<String, String>) -> (usize, usize) {
+    let size = params.get("size")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(config.pages.size);
+    let page = match params.get("page").and_then(|v| v.parse::<usize>().ok()) {
+        Some(page) => page.saturating_sub(1),
+        None if size == s.page_size => s.page,
+        None => 0,
+    };
+    (pages::clamp_page(s, page, size), size)
+}
+
+async fn get_results_page(
+    State(app): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Json<Value> {
+    let s = app.state.read();
+    let (page, size) = page_params(&s, &app.config, &params);
+    Json(pages::page_view(&s, page, size))
+}
+
+// Always `size` rows, blanks at the end of the last page
+async fn vmix_results_page(
+    State(app): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let s = app.state.read();
+    let (page, size) = page_params(&s, &app.config, &params);
+    xml(vmix::results_xml(&s, page * size, Some(size)))
 }
 
 async fn get_races(State(state): State<SharedState>) -> Json<Value> {
@@ -113,3 +486,47 @@ async fn get_races(State(state): State<SharedState>) -> Json<Value> {
     let races: Vec<_> = s.races.values().collect();
     Json(serde_json::to_value(races).unwrap())
 }
+
+async fn get_status(State(app): State<AppState>) -> Json<Value> {
+    Json(serde_json::json!({ "script_error": app.script_error }))
+}
+
+async fn get_sources(State(app): State<AppState>) -> Json<Value> {
+    Json(serde_json::json!(app.sources.list()))
+}
+
+async fn get_source_switches(State(app): State<AppState>) -> Json<Value> {
+    Json(serde_json::json!(app.sources.switches()))
+}
+
+#[derive(Deserialize)]
+struct RoleRequest {
+    role: SourceRole,
+}
+
+// {"role": "primary"} to hand the board to another Lynx PC
+async fn set_source_role(
+    State(app): State<AppState>,
+    Path(id): Path<u64>,
+    Json(req): Json<RoleRequest>,
+) -> StatusCode {
+    if app.sources.set_role(id, req.role) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_root_route_is_reserved() {
+        let names = reserved_names();
+        for name in ["status", "live", "sources", "vmix", "ws", "events", "channels"] {
+            assert!(names.contains(&name), "{} missing from {:?}", name, names);
+        }
+        assert!(!names.contains(&""));
+    }
+}